@@ -0,0 +1,113 @@
+use crate::{Document, Position};
+
+use std::{mem, path::Path};
+
+/// An open document along with where the cursor and view were in it.
+#[derive(Default)]
+pub struct Buffer {
+    pub document: Document,
+    pub cursor: Position,
+    /// The view's offset, so that switching back shows the same part of
+    /// the document.
+    pub offset: Position,
+}
+
+/// The open buffers, in the order switching to the next or previous one
+/// goes through them.
+///
+/// The editor works on the active buffer directly, so its slot holds an
+/// empty placeholder while it is active. Switching hands the active buffer
+/// back in exchange for the next one.
+pub struct BufferList {
+    buffers: Vec<Buffer>,
+    active: usize,
+}
+
+impl Default for BufferList {
+    fn default() -> Self {
+        Self {
+            buffers: vec![Buffer::default()],
+            active: 0,
+        }
+    }
+}
+
+impl BufferList {
+    /// How many buffers are open, the active one included. There is always
+    /// at least one.
+    pub fn count(&self) -> usize {
+        self.buffers.len()
+    }
+
+    pub const fn active_index(&self) -> usize {
+        self.active
+    }
+
+    /// Index of the buffer after the active one, wrapping around.
+    pub fn next_index(&self) -> usize {
+        self.active.saturating_add(1) % self.count()
+    }
+
+    /// Index of the buffer before the active one, wrapping around.
+    pub fn previous_index(&self) -> usize {
+        self.active
+            .checked_sub(1)
+            .unwrap_or_else(|| self.count().saturating_sub(1))
+    }
+
+    /// Adds an empty buffer right after the active one and gives its index.
+    /// It becomes active on switching to it.
+    pub fn add(&mut self) -> usize {
+        let index = self.active.saturating_add(1);
+        self.buffers.insert(index, Buffer::default());
+        index
+    }
+
+    /// Stores `active` as the active buffer and gives the buffer at `index`,
+    /// which becomes active instead. If `index` is the active one or out of
+    /// range, nothing changes and `active` is given back.
+    pub fn switch(&mut self, index: usize, active: Buffer) -> Buffer {
+        if index == self.active || index >= self.count() {
+            return active;
+        }
+        if let Some(slot) = self.buffers.get_mut(self.active) {
+            *slot = active;
+        }
+        self.active = index;
+        self.buffers
+            .get_mut(index)
+            .map(mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Drops the active buffer and makes the one before it active, or the
+    /// new first one if it was first. Gives that buffer, or `None` if the
+    /// active buffer was the only one, in which case it stays.
+    pub fn close_active(&mut self) -> Option<Buffer> {
+        if self.count() < 2 {
+            return None;
+        }
+        self.buffers.remove(self.active);
+        self.active = self.active.saturating_sub(1);
+        self.buffers.get_mut(self.active).map(mem::take)
+    }
+
+    /// Index of the inactive buffer holding the file at `path`, if any.
+    pub fn find(&self, path: &Path) -> Option<usize> {
+        self.buffers.iter().position(|buffer| {
+            buffer
+                .document
+                .file_name
+                .as_deref()
+                .map_or(false, |file_name| Path::new(file_name) == path)
+        })
+    }
+
+    /// How many of the inactive buffers have unsaved changes.
+    pub fn dirty_count(&self) -> usize {
+        self.buffers
+            .iter()
+            .filter(|buffer| buffer.document.is_dirty())
+            .count()
+    }
+}
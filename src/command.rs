@@ -0,0 +1,89 @@
+use crate::{picker, Action};
+
+use std::cmp::Reverse;
+
+/// An action under the name it is listed by in the command palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Command {
+    pub name: &'static str,
+    pub action: Action,
+}
+
+/// The commands the command palette offers, in the order it lists them
+/// before anything is typed.
+#[derive(Clone)]
+pub struct CommandRegistry {
+    commands: Vec<Command>,
+}
+
+impl Default for CommandRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            commands: Vec::new(),
+        };
+        registry.register("Save", Action::Save);
+        registry.register("Open file", Action::OpenFile);
+        registry.register("Open recent file", Action::OpenRecent);
+        registry.register("Find", Action::Search);
+        registry.register("Search files", Action::ProjectSearch);
+        registry.register("Jump back", Action::JumpBack);
+        registry.register("Jump forward", Action::JumpForward);
+        registry.register("Highlight word", Action::HighlightWord);
+        registry.register("Toggle comment", Action::ToggleComment);
+        registry.register("Move line up", Action::MoveLineUp);
+        registry.register("Move line down", Action::MoveLineDown);
+        registry.register("Duplicate line", Action::DuplicateLine);
+        registry.register("Insert timestamp", Action::InsertTimestamp);
+        registry.register("Toggle fold", Action::ToggleFold);
+        registry.register("Remove control characters", Action::Sanitize);
+        registry.register("Remove duplicate lines", Action::DedupLines);
+        registry.register("Convert indentation", Action::Retab);
+        registry.register("Toggle byte order mark", Action::ToggleBom);
+        registry.register("Next line at indentation", Action::NextAtIndent);
+        registry
+            .register("Previous line at indentation", Action::PreviousAtIndent);
+        registry.register("Cargo check", Action::Check);
+        registry.register("Reload", Action::Reload);
+        registry.register("Record macro", Action::ToggleRecording);
+        registry.register("Replay macro", Action::ReplayMacro);
+        registry.register("Toggle line numbers", Action::ToggleLineNumbers);
+        registry.register("Toggle whitespace", Action::ToggleWhitespace);
+        registry.register("Toggle ruler", Action::ToggleRuler);
+        registry.register("Toggle current line", Action::ToggleCurrentLine);
+        registry.register("Next buffer", Action::NextBuffer);
+        registry.register("Previous buffer", Action::PreviousBuffer);
+        registry.register("Close buffer", Action::CloseBuffer);
+        registry.register("Quit", Action::Quit);
+        registry.register("Force quit", Action::ForceQuit);
+        registry
+    }
+}
+
+impl CommandRegistry {
+    /// Adds a command called `name`, replacing any command of that name.
+    pub fn register(&mut self, name: &'static str, action: Action) {
+        let command = Command { name, action };
+        match self
+            .commands
+            .iter_mut()
+            .find(|command| command.name == name)
+        {
+            Some(existing) => *existing = command,
+            None => self.commands.push(command),
+        }
+    }
+
+    /// The commands whose names fuzzily match `query`, best match first.
+    /// Every command matches an empty query.
+    pub fn matches(&self, query: &str) -> Vec<Command> {
+        let mut scored: Vec<(usize, Command)> = self
+            .commands
+            .iter()
+            .filter_map(|command| {
+                Some((picker::score(query, command.name)?, *command))
+            })
+            .collect();
+        scored.sort_by_key(|&(score, _)| Reverse(score));
+        scored.into_iter().map(|(_, command)| command).collect()
+    }
+}
@@ -0,0 +1,353 @@
+use crate::{
+    parse_action, parse_key, Bell, FileType, FinalNewline, HighlightingOptions,
+    IndentStyle, KeyMap,
+};
+
+use chrono::format::{Item, StrftimeItems};
+use encoding_rs::{Encoding, UTF_8};
+use serde::Deserialize;
+use termion::color;
+use thiserror::Error;
+
+use std::{collections::BTreeMap, env, fs, io, path::PathBuf};
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("could not read {}: {}", .0.display(), .1)]
+    Io(PathBuf, #[source] io::Error),
+    #[error("invalid config {}: {}", .0.display(), .1)]
+    Parse(PathBuf, #[source] toml::de::Error),
+    #[error("unknown encoding in {}: {}", .0.display(), .1)]
+    UnknownEncoding(PathBuf, String),
+    #[error("invalid timestamp format in {}: {}", .0.display(), .1)]
+    InvalidTimestampFormat(PathBuf, String),
+    #[error("invalid color in {}: {}", .0.display(), .1)]
+    InvalidColor(PathBuf, String),
+    #[error("unknown key in {}: {}", .0.display(), .1)]
+    UnknownKey(PathBuf, String),
+    #[error("unknown action in {}: {}", .0.display(), .1)]
+    UnknownAction(PathBuf, String),
+}
+
+/// User settings, read from `$XDG_CONFIG_HOME/hecto/config.toml` (or
+/// `~/.config/hecto/config.toml`).
+///
+/// ```toml
+/// [editor]
+/// auto_pair = false
+/// final_newline = "always" # or "never", or "preserve" (the default)
+/// backup = true # copy a file to name~ before first saving it
+/// atomic_save = true # write a temporary file and rename it into place
+/// hyperlinks = true # make URLs clickable in terminals that support it
+/// indent = { spaces = 4 } # or "tabs" (the default), unless the filetype
+///                         # has its own
+/// fallback_encoding = "windows-1252" # for files that aren't UTF-8
+/// recent_files = true # remember opened files, in recent_files_path or
+///                     # $XDG_STATE_HOME/hecto/recent
+/// timestamp_format = "%Y-%m-%d %H:%M" # strftime-style, ISO 8601 by default
+/// scroll_margin = 3 # lines kept visible above and below the cursor
+/// ruler = 80 # shade the first column past 80, and show it from the start
+/// bell = "audible" # or "visual" (the default), for failed operations
+/// current_line = true # shade the cursor's line, and show it from the start
+/// current_line_color = "#303030" # its background
+///
+/// [keys] # see keymap::parse_key for key names
+/// "ctrl-s" = "save" # actions in snake case, as in "move_line_up"
+/// "alt-q" = "none" # unbinds the key
+///
+/// [[filetype]]
+/// name = "Python"
+/// extensions = ["py"]
+/// files = ["SConstruct", "*.pyw"] # exact names and globs
+/// comment = "#"
+/// string_delimiters = ['"', "'"]
+/// numbers = true
+/// primary_keywords = ["def", "class", "if", "else", "return"]
+/// keywords_case_insensitive = false
+/// operators = ["+", "-", "*", "/", "=", "==", "->", ":"]
+/// indent = { spaces = 4 } # instead of the editor's indent
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub editor: EditorConfig,
+    /// Key names mapped to the actions that replace their default ones.
+    pub keys: BTreeMap<String, String>,
+    #[serde(rename = "filetype")]
+    pub filetypes: Vec<FileTypeConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct EditorConfig {
+    pub auto_pair: bool,
+    pub final_newline: FinalNewline,
+    /// Whether a file is copied to `<name>~` before it is first saved.
+    pub backup: bool,
+    /// Whether files are saved through a temporary file renamed into
+    /// place.
+    pub atomic_save: bool,
+    pub hyperlinks: bool,
+    pub indent: IndentStyle,
+    /// Label of the encoding to read files in that have no byte order mark
+    /// and aren't valid UTF-8.
+    pub fallback_encoding: Option<String>,
+    pub recent_files: bool,
+    pub recent_files_path: Option<PathBuf>,
+    /// How inserted timestamps are written, in `strftime` style.
+    pub timestamp_format: String,
+    /// Lines to keep on screen above and below the cursor where there are
+    /// any, like vim's `scrolloff`.
+    pub scroll_margin: usize,
+    /// Display column to draw the ruler at. The ruler is shown from the
+    /// start if this is set, and at column 80 when toggled on otherwise.
+    pub ruler: Option<usize>,
+    pub bell: Bell,
+    /// Whether the cursor's line is shaded from the start. It can be
+    /// toggled either way.
+    pub current_line: bool,
+    /// Background of the cursor's line, as `#rrggbb`.
+    pub current_line_color: Option<String>,
+}
+
+impl Default for EditorConfig {
+    fn default() -> Self {
+        Self {
+            auto_pair: true,
+            final_newline: FinalNewline::default(),
+            backup: false,
+            atomic_save: false,
+            hyperlinks: false,
+            indent: IndentStyle::default(),
+            fallback_encoding: None,
+            recent_files: true,
+            recent_files_path: None,
+            timestamp_format: String::from("%Y-%m-%dT%H:%M:%S%:z"),
+            scroll_margin: 0,
+            ruler: None,
+            bell: Bell::default(),
+            current_line: false,
+            current_line_color: None,
+        }
+    }
+}
+
+impl EditorConfig {
+    /// The encoding `fallback_encoding` names, or UTF-8 if it isn't set.
+    /// [`Config::load`] has checked that the name is known.
+    pub fn fallback_encoding(&self) -> &'static Encoding {
+        self.fallback_encoding
+            .as_ref()
+            .and_then(|label| Encoding::for_label(label.as_bytes()))
+            .unwrap_or(UTF_8)
+    }
+
+    /// The color `current_line_color` gives, if it is set. [`Config::load`]
+    /// has checked that it parses.
+    pub fn current_line_color(&self) -> Option<color::Rgb> {
+        self.current_line_color.as_deref().and_then(parse_color)
+    }
+}
+
+/// A filetype described in the config. Every highlighting feature is off
+/// unless enabled here.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct FileTypeConfig {
+    pub name: String,
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    /// Exact file names, such as `Jenkinsfile`, or globs matched against
+    /// the whole file name, such as `Dockerfile.*`.
+    #[serde(default)]
+    pub files: Vec<String>,
+    #[serde(default)]
+    pub numbers: bool,
+    #[serde(default)]
+    pub characters: bool,
+    #[serde(default)]
+    pub variables: bool,
+    #[serde(default)]
+    pub make_variables: bool,
+    #[serde(default)]
+    pub targets: bool,
+    #[serde(default)]
+    pub tags: bool,
+    #[serde(default)]
+    pub string_delimiters: Vec<char>,
+    #[serde(default)]
+    pub raw_string_delimiters: Vec<char>,
+    pub long_string: Option<(String, String)>,
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub extra_comments: Vec<String>,
+    pub multiline_comment: Option<(String, String)>,
+    #[serde(default)]
+    pub multiline_comment_at_line_start: bool,
+    #[serde(default)]
+    pub symbols: bool,
+    #[serde(default)]
+    pub key_values: bool,
+    #[serde(default)]
+    pub primary_keywords: Vec<String>,
+    #[serde(default)]
+    pub secondary_keywords: Vec<String>,
+    #[serde(default)]
+    pub keywords_case_insensitive: bool,
+    #[serde(default)]
+    pub keywords_at_line_start: bool,
+    #[serde(default)]
+    pub operators: Vec<String>,
+    /// Overrides the editor's `indent` for files of this type.
+    pub indent: Option<IndentStyle>,
+    pub tab_width: Option<usize>,
+}
+
+impl Config {
+    /// Reads the config file. A missing file gives the default config.
+    pub fn load() -> Result<Self, ConfigError> {
+        let path = match config_path() {
+            Some(path) => path,
+            None => return Ok(Self::default()),
+        };
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                return Ok(Self::default());
+            },
+            Err(error) => return Err(ConfigError::Io(path, error)),
+        };
+        let config: Self = toml::from_str(&contents)
+            .map_err(|error| ConfigError::Parse(path.clone(), error))?;
+        if let Some(label) = &config.editor.fallback_encoding {
+            if Encoding::for_label(label.as_bytes()).is_none() {
+                return Err(ConfigError::UnknownEncoding(path, label.clone()));
+            }
+        }
+        let format = &config.editor.timestamp_format;
+        if StrftimeItems::new(format).any(|item| item == Item::Error) {
+            return Err(ConfigError::InvalidTimestampFormat(
+                path,
+                format.clone(),
+            ));
+        }
+        if let Some(color) = &config.editor.current_line_color {
+            if parse_color(color).is_none() {
+                return Err(ConfigError::InvalidColor(path, color.clone()));
+            }
+        }
+        for (key, action) in &config.keys {
+            if parse_key(key).is_none() {
+                return Err(ConfigError::UnknownKey(path, key.clone()));
+            }
+            if parse_action(action).is_none() {
+                return Err(ConfigError::UnknownAction(path, action.clone()));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Applies the `[keys]` table on top of `keymap`. [`Config::load`] has
+    /// checked the key and action names.
+    pub fn apply_keys(&self, keymap: &mut KeyMap) {
+        for (key, action) in &self.keys {
+            if let (Some(key), Some(action)) =
+                (parse_key(key), parse_action(action))
+            {
+                keymap.set(key, action);
+            }
+        }
+    }
+
+    /// Registers the configured filetypes, taking precedence over the
+    /// built-in ones for the same extensions, file names or globs.
+    pub fn register_filetypes(&self) {
+        for filetype in &self.filetypes {
+            let patterns: Vec<String> = filetype
+                .extensions
+                .iter()
+                .map(|extension| format!("*.{}", extension))
+                .chain(filetype.files.iter().cloned())
+                .collect();
+            let patterns: Vec<&str> =
+                patterns.iter().map(String::as_str).collect();
+            FileType::register(
+                &patterns,
+                &filetype.name,
+                filetype.highlighting_options(),
+                filetype.indent,
+                filetype.tab_width,
+            );
+        }
+    }
+}
+
+impl FileTypeConfig {
+    fn highlighting_options(&self) -> HighlightingOptions {
+        HighlightingOptions {
+            numbers: self.numbers,
+            string_delimiters: self.string_delimiters.clone(),
+            raw_string_delimiters: self.raw_string_delimiters.clone(),
+            long_string: self.long_string.clone(),
+            characters: self.characters,
+            comment_leader: self.comment.clone(),
+            extra_comment_leaders: self.extra_comments.clone(),
+            multiline_comment: self.multiline_comment.clone(),
+            multiline_comment_at_line_start: self
+                .multiline_comment_at_line_start,
+            variables: self.variables,
+            make_variables: self.make_variables,
+            targets: self.targets,
+            symbols: self.symbols,
+            key_values: self.key_values,
+            tags: self.tags,
+            primary_keywords: self.primary_keywords.clone(),
+            secondary_keywords: self.secondary_keywords.clone(),
+            keywords_case_insensitive: self.keywords_case_insensitive,
+            keywords_at_line_start: self.keywords_at_line_start,
+            operators: self.operators.clone(),
+        }
+    }
+}
+
+fn config_path() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"))
+        })?;
+    Some(config_dir.join("hecto").join("config.toml"))
+}
+
+/// Parses a color written as `#rrggbb`.
+fn parse_color(color: &str) -> Option<color::Rgb> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |range| u8::from_str_radix(hex.get(range)?, 16).ok();
+    Some(color::Rgb(channel(0..2)?, channel(2..4)?, channel(4..6)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Action;
+    use termion::event::Key;
+
+    #[test]
+    fn keys_override_and_unbind_defaults() {
+        let config: Config = toml::from_str(
+            "[keys]\n\"f2\" = \"save\"\n\"ctrl-s\" = \"none\"\n",
+        )
+        .unwrap();
+        let mut keymap = KeyMap::default();
+        config.apply_keys(&mut keymap);
+        assert_eq!(keymap.action(Key::F(2)), Some(Action::Save));
+        assert_eq!(keymap.action(Key::Ctrl('s')), None);
+        assert_eq!(keymap.action(Key::Ctrl('q')), Some(Action::Quit));
+    }
+}
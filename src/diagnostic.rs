@@ -0,0 +1,126 @@
+use crate::{highlighting, Document};
+
+use serde::Deserialize;
+
+use std::{cmp, path::Path};
+
+/// How serious a [`Diagnostic`] is. Later variants are more serious, so
+/// the most serious of several can be found with `max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub(crate) const fn highlighting_type(self) -> highlighting::Type {
+        match self {
+            Self::Warning => highlighting::Type::Warning,
+            Self::Error => highlighting::Type::Error,
+        }
+    }
+}
+
+/// A problem an external tool, such as a compiler, reported in a document.
+/// Columns are grapheme indexes into the row at `line`, with `col_end`
+/// exclusive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub col_start: usize,
+    pub col_end: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// A line of `cargo check --message-format=json` output. Only compiler
+/// messages carry a `message`.
+#[derive(Deserialize)]
+struct CargoMessage {
+    reason: String,
+    message: Option<CompilerMessage>,
+}
+
+#[derive(Deserialize)]
+struct CompilerMessage {
+    message: String,
+    level: String,
+    spans: Vec<Span>,
+}
+
+/// Where a compiler message points. Lines and columns count from 1, and
+/// columns count chars, with `column_end` exclusive.
+#[derive(Deserialize)]
+struct Span {
+    file_name: String,
+    line_start: usize,
+    line_end: usize,
+    column_start: usize,
+    column_end: usize,
+    is_primary: bool,
+}
+
+/// The errors and warnings in the output of
+/// `cargo check --message-format=json` that point into `document`, the
+/// file at `path`. Span paths are relative to the workspace root, so they
+/// match any path they are a suffix of. Lines that aren't compiler
+/// messages, notes and help, and spans in other files are skipped. A span
+/// over several lines gives a diagnostic for each of them.
+pub fn cargo_diagnostics(
+    output: &str,
+    path: &Path,
+    document: &Document,
+) -> Vec<Diagnostic> {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let mut diagnostics = Vec::new();
+
+    for line in output.lines() {
+        let message = match serde_json::from_str::<CargoMessage>(line) {
+            Ok(CargoMessage {
+                reason,
+                message: Some(message),
+            }) if reason == "compiler-message" => message,
+            _ => continue,
+        };
+        let severity = match message.level.as_str() {
+            "warning" => Severity::Warning,
+            level if level.starts_with("error") => Severity::Error,
+            _ => continue,
+        };
+
+        for span in &message.spans {
+            if !span.is_primary || !path.ends_with(&span.file_name) {
+                continue;
+            }
+            let first = span.line_start.saturating_sub(1);
+            let last = span.line_end.saturating_sub(1);
+            for line in first..=last {
+                let row = match document.row(line) {
+                    Some(row) => row,
+                    None => break,
+                };
+                let col_start = if line == first {
+                    row.index_of_char(span.column_start.saturating_sub(1))
+                } else {
+                    0
+                };
+                let col_end = if line == last {
+                    row.index_of_char(span.column_end.saturating_sub(1))
+                } else {
+                    row.len()
+                };
+                // Empty spans, such as where a missing `;` belongs, still
+                // mark the grapheme they point at.
+                let col_end = cmp::max(col_end, col_start.saturating_add(1));
+                diagnostics.push(Diagnostic {
+                    line,
+                    col_start,
+                    col_end,
+                    severity,
+                    message: message.message.clone(),
+                });
+            }
+        }
+    }
+    diagnostics
+}
@@ -1,42 +1,399 @@
-use crate::{FileType, Highlighter, Position, Row, SearchDirection};
+use crate::{
+    highlighting,
+    row::{control_picture, grapheme_width, TAB_WIDTH},
+    BlockSelection, Diagnostic, FileEncoding, FileType, Highlighter, Position,
+    Row, SearchDirection, Selection,
+};
 use anyhow::Result;
+use encoding_rs::{Encoding, UTF_8};
+use serde::Deserialize;
 use std::{
-    fs,
+    cell::Cell,
+    cmp, fmt, fs,
     io::{self, Write},
+    iter, mem,
+    path::{Path, PathBuf},
+    time::SystemTime,
 };
+use thiserror::Error;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Controls how [`Document::save`] writes to disk. Everything is opt-in.
+#[derive(Default, Clone, Copy)]
+pub struct SaveOptions {
+    /// Copy the file to `<name>~` before the first save of the session.
+    pub backup: bool,
+    /// Write to a temporary file next to the target and rename it into
+    /// place, so a crash mid-save never leaves a truncated file behind.
+    pub atomic: bool,
+    pub final_newline: FinalNewline,
+}
+
+/// Whether a saved file ends with a newline after its last line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FinalNewline {
+    /// Keep the file the way it was when opened. New files get a final
+    /// newline, since POSIX defines a line as ending in one and tools like
+    /// `wc -l` or `cat` treat a missing one as a broken last line.
+    Preserve,
+    Always,
+    Never,
+}
+
+impl Default for FinalNewline {
+    fn default() -> Self {
+        Self::Preserve
+    }
+}
+
+/// What pressing Tab inserts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndentStyle {
+    Tabs,
+    /// Soft tabs: spaces up to the next multiple of the given width.
+    Spaces(usize),
+}
+
+impl Default for IndentStyle {
+    fn default() -> Self {
+        Self::Tabs
+    }
+}
+
+/// What a document holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferKind {
+    File,
+    /// A read-only listing of a directory's entries, one per line, with
+    /// `../` first and a `/` after the names of directories.
+    Directory,
+}
+
+impl Default for BufferKind {
+    fn default() -> Self {
+        Self::File
+    }
+}
+
+/// Size statistics of a document, as shown in the status bar.
+#[derive(Default, Clone, Copy)]
+pub struct DocStats {
+    pub lines: usize,
+    pub graphemes: usize,
+    /// Runs of characters between separators.
+    pub words: usize,
+    /// Size of the file `save()` would write, line endings included.
+    pub bytes: usize,
+}
+
+#[derive(Debug, Error)]
+pub enum DocumentError {
+    #[error("document has unsaved changes")]
+    Dirty,
+    #[error("document has no file name")]
+    NoFileName,
+    #[error("{0} is a symbolic link to a file that doesn't exist")]
+    BrokenSymlink(String),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
 
+/// The rows of a file being edited.
+///
+/// The cursor can sit on the line just past the last row, so that there is
+/// always somewhere to type. Editing there behaves as if that line were an
+/// empty row, which makes an empty document act like a single empty row:
+/// inserting creates the row, while deleting, searching forward and
+/// selecting find nothing there.
 #[derive(Default)]
-pub struct Document<'a> {
+#[allow(clippy::struct_excessive_bools)]
+pub struct Document {
     rows: Vec<Row>,
     pub file_name: Option<String>,
+    kind: BufferKind,
+    /// Whether the document was edited since it was last read or written.
     dirty: bool,
-    file_type: FileType<'a>,
+    /// The text of the rows when the document was last read or written.
+    saved_lines: Vec<String>,
+    /// Whether there was a byte order mark then.
+    saved_bom: bool,
+    /// Whether the rows differ from the saved ones, once checked after the
+    /// last edit.
+    differs_from_saved: Cell<Option<bool>>,
+    read_only: bool,
+    save_options: SaveOptions,
+    indent_style: IndentStyle,
+    backed_up: bool,
+    file_type: FileType,
+    stats: Cell<Option<DocStats>>,
+    /// Modification time of the file when it was last read or written.
+    disk_mtime: Option<SystemTime>,
+    /// Whether the file lacked a newline after its last line when read.
+    missing_final_newline: bool,
+    encoding: FileEncoding,
+    /// Word whose occurrences stay marked, independent of any search.
+    match_word: Option<String>,
+    /// Word the rows were last marked with, so they can be redone when it
+    /// changes.
+    marked_word: Option<String>,
+    /// Sorted by line, so each row's are a slice of them.
+    diagnostics: Vec<Diagnostic>,
+    /// Folded ranges of lines as `(first, last)`, sorted and apart. The
+    /// first line of a fold stays visible and stands for the rest.
+    folds: Vec<(usize, usize)>,
+}
+
+/// Writes the rows joined by newlines, with no newline after the last one
+/// whatever saving would add.
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, row) in self.rows.iter().enumerate() {
+            if index > 0 {
+                f.write_str("\n")?;
+            }
+            fmt::Display::fmt(row, f)?;
+        }
+        Ok(())
+    }
 }
 
-impl<'a> Document<'a> {
+/// Shows the state that decides how the document behaves and saves, and
+/// its lines, leaving out caches and highlighting.
+#[allow(clippy::missing_fields_in_debug)]
+impl fmt::Debug for Document {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Document")
+            .field("file_name", &self.file_name)
+            .field("kind", &self.kind)
+            .field("file_type", &self.file_type.name())
+            .field("dirty", &self.dirty)
+            .field("read_only", &self.read_only)
+            .field("encoding", &self.encoding.name())
+            .field("indent_style", &self.indent_style)
+            .field("missing_final_newline", &self.missing_final_newline)
+            .field("lines", &self.lines().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Document {
     pub fn open(filename: &str) -> Result<Self, io::Error> {
-        let contents = fs::read_to_string(filename)?;
-        let file_type = FileType::from(filename);
-        // let mut start_with_comment = false;
-        let mut rows = vec![];
+        Self::open_with_encoding(filename, UTF_8)
+    }
 
-        for value in contents.lines() {
-            // let mut row = Row::from(value);
-            rows.push(Row::from(value));
-            // start_with_comment = row.highlight(
-            //     &file_type.highlighting_options(),
-            //     None,
-            //     start_with_comment,
-            // );
-            // rows.push(row);
+    /// Opens `filename`, decoding it as `fallback` if it has no byte order
+    /// mark and isn't valid UTF-8. Saving encodes the file the same way.
+    /// A directory opens as a listing of its entries.
+    pub fn open_with_encoding(
+        filename: &str,
+        fallback: &'static Encoding,
+    ) -> Result<Self, io::Error> {
+        if Path::new(filename).is_dir() {
+            return Self::open_directory(filename);
         }
+        let (rows, missing_final_newline, encoding) =
+            read_rows(filename, fallback)?;
+        let file_type = FileType::from(filename);
+        let read_only =
+            fs::OpenOptions::new().append(true).open(filename).is_err();
 
-        Ok(Self {
+        let mut document = Self {
             rows,
             file_name: Some(filename.to_string()),
+            kind: BufferKind::File,
             dirty: false,
+            saved_lines: Vec::new(),
+            saved_bom: false,
+            differs_from_saved: Cell::new(None),
+            read_only,
+            save_options: SaveOptions::default(),
+            indent_style: IndentStyle::default(),
+            backed_up: false,
             file_type,
-        })
+            stats: Cell::new(None),
+            disk_mtime: mtime(filename),
+            missing_final_newline,
+            encoding,
+            match_word: None,
+            marked_word: None,
+            diagnostics: Vec::new(),
+            folds: Vec::new(),
+        };
+        document.mark_saved();
+        Ok(document)
+    }
+
+    /// Lists the entries of the directory `dirname`, read-only.
+    fn open_directory(dirname: &str) -> Result<Self, io::Error> {
+        let mut document = Self {
+            rows: list_directory(dirname)?,
+            file_name: Some(dirname.to_string()),
+            kind: BufferKind::Directory,
+            read_only: true,
+            disk_mtime: mtime(dirname),
+            ..Self::default()
+        };
+        document.mark_saved();
+        Ok(document)
+    }
+
+    pub const fn kind(&self) -> BufferKind {
+        self.kind
+    }
+
+    /// The path of the entry listed on `line` of a directory listing, or
+    /// `None` if the document isn't one or the line doesn't exist.
+    pub fn entry_at(&self, line: usize) -> Option<PathBuf> {
+        if self.kind != BufferKind::Directory {
+            return None;
+        }
+        let dir = Path::new(self.file_name.as_deref()?);
+        let name = self.row(line)?.string.trim_end_matches('/');
+        if name == ".." {
+            // Going up from a name just drops it, but `.`, `..` and the
+            // root have none to drop.
+            return Some(match dir.file_name() {
+                Some(_) => dir
+                    .parent()
+                    .filter(|parent| !parent.as_os_str().is_empty())
+                    .map_or_else(|| PathBuf::from("."), Path::to_path_buf),
+                None if dir == Path::new(".") => PathBuf::from(".."),
+                None => dir.join(".."),
+            });
+        }
+        Some(dir.join(name))
+    }
+
+    /// A clean buffer with no file behind it yet, to be named on first save.
+    pub fn new_scratch() -> Self {
+        let mut document = Self::default();
+        document.mark_saved();
+        document
+    }
+
+    /// Names the file the document is saved to, picking the filetype to
+    /// match.
+    pub fn set_file_name(&mut self, file_name: &str) {
+        self.file_name = Some(file_name.to_string());
+        self.file_type = FileType::from(file_name);
+    }
+
+    /// Opens `filename` for browsing only; edits and saves are refused.
+    pub fn open_readonly(filename: &str) -> Result<Self, io::Error> {
+        let mut document = Self::open(filename)?;
+        document.read_only = true;
+        Ok(document)
+    }
+
+    pub const fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Sets the indent style to use if the filetype doesn't call for one.
+    pub fn set_indent_style(&mut self, style: IndentStyle) {
+        self.indent_style = style;
+    }
+
+    /// The filetype's indent style, or the one set for the document if the
+    /// filetype has none.
+    pub fn indent_style(&self) -> IndentStyle {
+        self.file_type.indent_style().unwrap_or(self.indent_style)
+    }
+
+    /// How many columns a tab is taken to span when converting between
+    /// tabs and spaces: the filetype's tab width, or else the width of soft
+    /// tabs or the width tabs are drawn at.
+    pub fn tab_width(&self) -> usize {
+        self.file_type
+            .tab_width()
+            .unwrap_or(match self.indent_style() {
+                IndentStyle::Spaces(width) => width,
+                IndentStyle::Tabs => TAB_WIDTH,
+            })
+    }
+
+    pub fn set_save_options(&mut self, options: SaveOptions) {
+        self.save_options = options;
+    }
+
+    /// Replaces the contents with the file on disk. Fails with
+    /// [`DocumentError::Dirty`] if there are unsaved changes, unless
+    /// `discard_changes` is set.
+    pub fn reload(
+        &mut self,
+        discard_changes: bool,
+    ) -> Result<(), DocumentError> {
+        if self.is_dirty() && !discard_changes {
+            return Err(DocumentError::Dirty);
+        }
+        let file_name =
+            self.file_name.as_deref().ok_or(DocumentError::NoFileName)?;
+        if self.kind == BufferKind::Directory {
+            self.rows = list_directory(file_name)?;
+            self.folds.clear();
+            self.disk_mtime = mtime(file_name);
+            self.mark_saved();
+            self.stats.set(None);
+            return Ok(());
+        }
+
+        let (rows, missing_final_newline, encoding) =
+            read_rows(file_name, self.encoding.encoding())?;
+        self.rows = rows;
+        self.missing_final_newline = missing_final_newline;
+        self.encoding = encoding;
+        self.folds.clear();
+        self.disk_mtime = mtime(file_name);
+        self.mark_saved();
+        self.stats.set(None);
+        Ok(())
+    }
+
+    /// The encoding the file was read in and will be saved in.
+    pub const fn encoding(&self) -> FileEncoding {
+        self.encoding
+    }
+
+    /// Whether the file starts with a byte order mark. The mark is kept out
+    /// of the rows and written back on saving.
+    pub const fn has_bom(&self) -> bool {
+        self.encoding.has_bom()
+    }
+
+    /// Adds or drops the byte order mark saving writes. Returns `false` if
+    /// the document is read-only or not UTF-8, where it can't change.
+    pub fn set_bom(&mut self, bom: bool) -> bool {
+        if self.read_only {
+            return false;
+        }
+        match self.encoding.with_bom(bom) {
+            Some(encoding) => {
+                if encoding != self.encoding {
+                    self.encoding = encoding;
+                    self.mark_dirty();
+                }
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Whether the file on disk changed since it was last read or written.
+    /// A file that has since been deleted is reported as a `NotFound` error.
+    pub fn externally_modified(&self) -> Result<bool, io::Error> {
+        match &self.file_name {
+            Some(file_name) => {
+                let modified = fs::metadata(file_name)?.modified()?;
+                Ok(self.disk_mtime != Some(modified))
+            },
+            None => Ok(false),
+        }
     }
 
     pub fn file_type(&self) -> String {
@@ -47,6 +404,56 @@ impl<'a> Document<'a> {
         self.rows.get(index)
     }
 
+    /// The rows, first to last.
+    pub fn rows(&self) -> impl Iterator<Item = &Row> {
+        self.rows.iter()
+    }
+
+    /// The rows from `line` on. Empty if `line` is past the last row.
+    pub fn iter_from(&self, line: usize) -> impl Iterator<Item = &Row> {
+        self.rows.iter().skip(line)
+    }
+
+    /// The text of the row `y`, without its line ending.
+    pub fn line_str(&self, y: usize) -> Option<&str> {
+        self.row(y).map(|row| row.string.as_str())
+    }
+
+    /// The char at `pos`, where `pos.x` counts graphemes as cursor positions
+    /// do. For a grapheme made of several chars, such as `e` followed by a
+    /// combining accent, this is its first char, the base the others modify.
+    /// `None` if `pos` is at or past the end of its row, or past the last
+    /// row.
+    pub fn char_at(&self, pos: &Position) -> Option<char> {
+        self.row(pos.y)?.char_at(pos.x)
+    }
+
+    /// The text of each row, without line endings.
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.rows.iter().map(|row| row.string.as_str())
+    }
+
+    /// The nearest line after `from`, or before it unless `forward`, that
+    /// is indented by `indent` graphemes as [`Row::indentation`] counts
+    /// them. Blank lines, including ones of only whitespace, are skipped.
+    pub fn next_line_at_indent(
+        &self,
+        from: usize,
+        indent: usize,
+        forward: bool,
+    ) -> Option<usize> {
+        let at_indent = |y: &usize| {
+            self.rows.get(*y).map_or(false, |row| {
+                !row.string.trim().is_empty() && row.indentation() == indent
+            })
+        };
+        if forward {
+            (from.saturating_add(1)..self.rows.len()).find(at_indent)
+        } else {
+            (0..cmp::min(from, self.rows.len())).rev().find(at_indent)
+        }
+    }
+
     pub fn is_empty(&self) -> bool {
         self.rows.is_empty()
     }
@@ -55,7 +462,58 @@ impl<'a> Document<'a> {
         self.rows.len()
     }
 
+    /// Line, grapheme, word and byte counts. Computed on first use and cached
+    /// until the next edit.
+    pub fn stats(&self) -> DocStats {
+        if let Some(stats) = self.stats.get() {
+            return stats;
+        }
+
+        let stats =
+            self.rows
+                .iter()
+                .fold(DocStats::default(), |stats, row| DocStats {
+                    lines: stats.lines.saturating_add(1),
+                    graphemes: stats.graphemes.saturating_add(row.len()),
+                    words: stats.words.saturating_add(row.word_count()),
+                    bytes: stats
+                        .bytes
+                        .saturating_add(row.as_bytes().len())
+                        .saturating_add(1),
+                });
+        let bytes = if !self.encoding.is_plain_utf8() {
+            self.encoding
+                .encode(&self.contents())
+                .map_or(stats.bytes, |bytes| bytes.len())
+        } else if self.final_newline() || stats.lines == 0 {
+            stats.bytes
+        } else {
+            stats.bytes.saturating_sub(1)
+        };
+        let stats = DocStats { bytes, ..stats };
+        self.stats.set(Some(stats));
+        stats
+    }
+
+    fn mark_dirty(&mut self) {
+        self.dirty = true;
+        self.differs_from_saved.set(None);
+        self.stats.set(None);
+    }
+
+    /// Takes the current rows as the ones on disk, which edits are compared
+    /// against.
+    fn mark_saved(&mut self) {
+        self.dirty = false;
+        self.saved_lines = self.lines().map(String::from).collect();
+        self.saved_bom = self.has_bom();
+        self.differs_from_saved.set(None);
+    }
+
     fn insert_newline(&mut self, at: &Position) {
+        if self.read_only {
+            return;
+        }
         if at.y == self.rows.len() {
             self.rows.push(Row::default());
             return;
@@ -65,15 +523,18 @@ impl<'a> Document<'a> {
         let current_row = &mut self.rows[at.y];
         let new_row = current_row.split(at.x);
 
-        #[allow(clippy::integer_arithmetic)]
+        #[allow(clippy::arithmetic_side_effects)]
         self.rows.insert(at.y + 1, new_row);
+        self.rows_replaced(at.y, 1, 2);
     }
 
-    pub(crate) fn insert(&mut self, at: &Position, c: char) {
-        if at.y > self.rows.len() {
-            return;
+    /// Inserts `c` at `at`. Returns `false` if nothing was inserted because
+    /// the position is out of bounds or the document is read-only.
+    pub(crate) fn insert(&mut self, at: &Position, c: char) -> bool {
+        if self.read_only || at.y > self.rows.len() {
+            return false;
         }
-        self.dirty = true;
+        self.mark_dirty();
 
         if c == '\n' {
             self.insert_newline(at);
@@ -87,47 +548,741 @@ impl<'a> Document<'a> {
             row.insert(at.x, c);
         }
 
-        self.unhighlight_rows(at.y);
+        true
     }
 
-    #[allow(clippy::integer_arithmetic, clippy::indexing_slicing)]
-    pub(crate) fn delete(&mut self, at: &Position) {
-        let len = self.rows.len();
-        if at.y >= len {
+    /// Inserts `s` at `at`, starting a new row at every newline, and returns
+    /// the position just past the inserted text. Each affected row is rebuilt
+    /// once, which makes this much cheaper than inserting `s` a character at
+    /// a time. Returns `at` unchanged if nothing was inserted because the
+    /// position is out of bounds or the document is read-only.
+    #[allow(clippy::arithmetic_side_effects, clippy::indexing_slicing)]
+    pub fn insert_str(&mut self, at: &Position, s: &str) -> Position {
+        if self.read_only || at.y > self.rows.len() || s.is_empty() {
+            return at.clone();
+        }
+        self.mark_dirty();
+
+        if at.y == self.rows.len() {
+            self.rows.push(Row::default());
+        }
+        let row = &mut self.rows[at.y];
+        let tail = row.split(at.x);
+
+        let mut lines: Vec<String> = s.split('\n').map(String::from).collect();
+        lines[0].insert_str(0, &row.string);
+        let last = lines.len() - 1;
+        let x = lines[last].graphemes(true).count();
+        lines[last].push_str(&tail.string);
+
+        self.rows.splice(
+            at.y..=at.y,
+            lines.iter().map(|line| Row::from(line.as_str())),
+        );
+        self.rows_replaced(at.y, 1, lines.len());
+        Position::new(x, at.y + last)
+    }
+
+    /// Breaks the line at `at`, which sits between an opening bracket and
+    /// its closer, into three: the closer moves to a line of its own with
+    /// the opener's indentation, and a line indented one level further goes
+    /// in between. Returns the end of that middle line, or `at` unchanged if
+    /// nothing was inserted because the position is out of bounds or the
+    /// document is read-only.
+    pub fn insert_newline_in_pair(&mut self, at: &Position) -> Position {
+        let indent = self
+            .row(at.y)
+            .map(|row| row.leading_whitespace().to_string())
+            .unwrap_or_default();
+        let level = match self.indent_style() {
+            IndentStyle::Tabs => String::from("\t"),
+            IndentStyle::Spaces(width) => " ".repeat(cmp::max(width, 1)),
+        };
+        let middle = self.insert_str(at, &format!("\n{}{}", indent, level));
+        self.insert_str(&middle, &format!("\n{}", indent));
+        middle
+    }
+
+    /// Inserts one level of indentation at `at` according to the indent
+    /// style, and returns the position just past it. With soft tabs, enough
+    /// spaces are inserted to reach the next multiple of the width.
+    pub fn insert_indent(&mut self, at: &Position) -> Position {
+        match self.indent_style() {
+            IndentStyle::Tabs => self.insert_str(at, "\t"),
+            IndentStyle::Spaces(width) => {
+                let width = cmp::max(width, 1);
+                #[allow(clippy::arithmetic_side_effects)]
+                let count = width - at.x % width;
+                self.insert_str(at, &" ".repeat(count))
+            },
+        }
+    }
+
+    /// Comments out the lines from `start_line` to `end_line` inclusive with
+    /// the filetype's line comment leader, placed after each line's
+    /// indentation. If every non-blank line is already commented, the
+    /// comments are removed instead. Returns `false` if nothing changed
+    /// because the filetype has no line comments, the range is empty or
+    /// blank, or the document is read-only.
+    #[allow(clippy::indexing_slicing, clippy::arithmetic_side_effects)]
+    pub fn toggle_comment(
+        &mut self,
+        start_line: usize,
+        end_line: usize,
+    ) -> bool {
+        let leader =
+            match self.file_type.highlighting_options().comment_leader() {
+                Some(leader) => leader,
+                None => return false,
+            };
+        let end_line = cmp::min(end_line.saturating_add(1), self.rows.len());
+        if self.read_only || start_line >= end_line {
+            return false;
+        }
+
+        let rows = &mut self.rows[start_line..end_line];
+        let split_indent = |row: &Row| {
+            let (indent, text) =
+                row.string.split_at(row.leading_whitespace().len());
+            (indent.to_string(), text.to_string())
+        };
+        let uncomment = rows
+            .iter()
+            .map(split_indent)
+            .filter(|(_, text)| !text.is_empty())
+            .all(|(_, text)| text.starts_with(leader));
+
+        let mut changed = false;
+        for row in rows.iter_mut() {
+            let (indent, text) = split_indent(row);
+            if text.is_empty() {
+                continue;
+            }
+            changed = true;
+            let text = if uncomment {
+                let text = &text[leader.len()..];
+                text.strip_prefix(' ').unwrap_or(text).to_string()
+            } else {
+                format!("{} {}", leader, text)
+            };
+            *row = Row::from(format!("{}{}", indent, text).as_str());
+        }
+
+        if changed {
+            self.mark_dirty();
+        }
+        changed
+    }
+
+    /// Appends `row` to the document as its last line. Does nothing if the
+    /// document is read-only.
+    pub fn push_row(&mut self, mut row: Row) {
+        if self.read_only {
             return;
         }
+        row.is_highlighted = false;
+        self.rows.push(row);
+        self.mark_dirty();
+    }
 
-        self.dirty = true;
+    /// Removes the line at `index` and returns it. Returns `None` if there
+    /// is no such line or the document is read-only.
+    pub fn remove_row(&mut self, index: usize) -> Option<Row> {
+        if self.read_only || index >= self.rows.len() {
+            return None;
+        }
+        let row = self.rows.remove(index);
+        self.rows_replaced(index, 1, 0);
+        // The line that took its place may now start in or out of a
+        // multiline comment; highlighting it again redoes the lines after
+        // it if so.
+        if let Some(next) = self.rows.get_mut(index) {
+            next.is_highlighted = false;
+        }
+        self.mark_dirty();
+        Some(row)
+    }
+
+    /// Inserts a copy of `line` directly below it. Returns the start of the
+    /// copy, or `None` if the line doesn't exist or the document is
+    /// read-only.
+    pub fn duplicate_line(&mut self, line: usize) -> Option<Position> {
+        self.duplicate_range(line, line)
+    }
+
+    /// Inserts a copy of the lines from `start_line` to `end_line`
+    /// inclusive directly below them. Returns the start of the copy, or
+    /// `None` if the range is empty or out of bounds, or the document is
+    /// read-only.
+    pub fn duplicate_range(
+        &mut self,
+        start_line: usize,
+        end_line: usize,
+    ) -> Option<Position> {
+        let end_line = end_line.saturating_add(1);
+        if self.read_only || start_line >= end_line || end_line > self.len() {
+            return None;
+        }
+
+        let copies: Vec<Row> = self
+            .iter_from(start_line)
+            .take(end_line.saturating_sub(start_line))
+            .map(|row| Row::from(row.string.as_str()))
+            .collect();
+        let count = copies.len();
+        self.rows.splice(end_line..end_line, copies);
+        self.rows_replaced(end_line, 0, count);
+        self.mark_dirty();
+        Some(Position::new(0, end_line))
+    }
+
+    /// Swaps `line` with the line above it. Returns `false` if there is no
+    /// line above or the document is read-only.
+    pub fn move_line_up(&mut self, line: usize) -> bool {
+        match line.checked_sub(1) {
+            Some(above) => self.swap_lines(above),
+            None => false,
+        }
+    }
+
+    /// Swaps `line` with the line below it. Returns `false` if there is no
+    /// line below or the document is read-only.
+    pub fn move_line_down(&mut self, line: usize) -> bool {
+        self.swap_lines(line)
+    }
+
+    /// Swaps the lines `upper` and `upper + 1`, dropping any fold that
+    /// includes either.
+    #[allow(clippy::indexing_slicing)]
+    fn swap_lines(&mut self, upper: usize) -> bool {
+        let lower = upper.saturating_add(1);
+        if self.read_only || lower >= self.rows.len() {
+            return false;
+        }
+        self.rows.swap(upper, lower);
+        // The rows' text is unchanged, but they may now start in or out of
+        // a multiline comment, or be under other diagnostics.
+        for row in &mut self.rows[upper..=lower] {
+            row.is_highlighted = false;
+        }
+        self.folds
+            .retain(|&(first, last)| last < upper || first > lower);
+        self.mark_dirty();
+        true
+    }
+
+    /// Inserts `text` at the left column of `sel` on each of its rows, so
+    /// that the insertions line up on screen. Rows that end before the
+    /// column are padded with spaces up to it, and a wide grapheme or tab
+    /// covering the column gets the text inserted before it. Returns
+    /// `false` if `text` spans lines, the rows are out of bounds or the
+    /// document is read-only.
+    pub fn insert_block(&mut self, sel: &BlockSelection, text: &str) -> bool {
+        let left = sel.top_left.x;
+        if text.contains('\n') {
+            return false;
+        }
+        self.edit_block(sel, |row| {
+            let width = row.display_width();
+            if width < left {
+                format!(
+                    "{}{}{}",
+                    row.string,
+                    " ".repeat(left.saturating_sub(width)),
+                    text
+                )
+            } else {
+                let index = row.index_at_column(left);
+                format!(
+                    "{}{}{}",
+                    row.slice(0, index),
+                    text,
+                    row.slice(index, usize::MAX)
+                )
+            }
+        })
+    }
+
+    /// Deletes the columns of `sel` from each of its rows. A grapheme that
+    /// straddles the left edge of the rectangle is deleted with it, and one
+    /// that straddles the right edge is kept. Returns `false` if the rows
+    /// are out of bounds or the document is read-only.
+    pub fn delete_block(&mut self, sel: &BlockSelection) -> bool {
+        let (left, right) = (sel.top_left.x, sel.bottom_right.x);
+        self.edit_block(sel, |row| {
+            format!(
+                "{}{}",
+                row.slice(0, row.index_at_column(left)),
+                row.slice(row.index_at_column(right), usize::MAX)
+            )
+        })
+    }
+
+    /// Replaces each row covered by `sel` with what `edit` makes of it.
+    fn edit_block<F>(&mut self, sel: &BlockSelection, edit: F) -> bool
+    where
+        F: Fn(&Row) -> String,
+    {
+        let top = sel.top_left.y;
+        let bottom = cmp::min(sel.bottom_right.y, self.len().saturating_sub(1));
+        if self.read_only || top > bottom || top >= self.len() {
+            return false;
+        }
+
+        let mut changed = false;
+        for row in self
+            .rows
+            .iter_mut()
+            .take(bottom.saturating_add(1))
+            .skip(top)
+        {
+            let edited = edit(row);
+            if edited != row.string {
+                *row = Row::from(edited.as_str());
+                changed = true;
+            }
+        }
+        if changed {
+            self.mark_dirty();
+        }
+        changed
+    }
+
+    /// The text covered by `sel`, with rows joined by newlines.
+    pub fn selected_text(&self, sel: &Selection) -> String {
+        let (start, end) = (sel.start(), sel.end());
+        let mut text = String::new();
+
+        for (y, row) in self.rows.iter().enumerate().skip(start.y) {
+            if y > end.y {
+                break;
+            }
+            let from = if y == start.y { start.x } else { 0 };
+            let to = if y == end.y { end.x } else { row.len() };
+            text.push_str(row.slice(from, to));
+            if y < end.y && y.saturating_add(1) < self.rows.len() {
+                text.push('\n');
+            }
+        }
+        text
+    }
+
+    /// Replaces the text from `start` up to `end` with `f` applied to it, one
+    /// row at a time. Rows are rebuilt from scratch, since a transform such
+    /// as case conversion can change their length. Returns `false` if
+    /// nothing was transformed because the range is empty or out of bounds,
+    /// or the document is read-only.
+    pub fn transform_range<F>(
+        &mut self,
+        start: &Position,
+        end: &Position,
+        f: F,
+    ) -> bool
+    where
+        F: Fn(&str) -> String,
+    {
+        let (start, end) = (cmp::min(start, end), cmp::max(start, end));
+        if self.read_only || start == end || start.y >= self.rows.len() {
+            return false;
+        }
+
+        for (y, row) in self.rows.iter_mut().enumerate().skip(start.y) {
+            if y > end.y {
+                break;
+            }
+            let from = if y == start.y { start.x } else { 0 };
+            let to = if y == end.y { end.x } else { row.len() };
+            let transformed = format!(
+                "{}{}{}",
+                row.slice(0, from),
+                f(row.slice(from, to)),
+                row.slice(to, usize::MAX)
+            );
+            *row = Row::from(transformed.as_str());
+        }
+
+        self.mark_dirty();
+        true
+    }
+
+    pub fn uppercase_range(
+        &mut self,
+        start: &Position,
+        end: &Position,
+    ) -> bool {
+        self.transform_range(start, end, str::to_uppercase)
+    }
+
+    pub fn lowercase_range(
+        &mut self,
+        start: &Position,
+        end: &Position,
+    ) -> bool {
+        self.transform_range(start, end, str::to_lowercase)
+    }
+
+    /// Removes lines from `start_line` to `end_line` inclusive that repeat
+    /// the line before them, like `uniq`, keeping the first of each run.
+    /// With `trimmed` set, lines that only differ in leading or trailing
+    /// whitespace count as repeats too. Returns how many lines were
+    /// removed, which is 0 if the range is empty or out of bounds, or the
+    /// document is read-only.
+    pub fn dedup_lines(
+        &mut self,
+        start_line: usize,
+        end_line: usize,
+        trimmed: bool,
+    ) -> usize {
+        let end_line = cmp::min(end_line.saturating_add(1), self.len());
+        if self.read_only || start_line >= end_line {
+            return 0;
+        }
+
+        let mut lines: Vec<Row> =
+            self.rows.drain(start_line..end_line).collect();
+        let old = lines.len();
+        lines.dedup_by(|row, previous| {
+            if trimmed {
+                row.string.trim() == previous.string.trim()
+            } else {
+                row.string == previous.string
+            }
+        });
+        let new = lines.len();
+        self.rows.splice(start_line..start_line, lines);
+        if new == old {
+            return 0;
+        }
+
+        self.rows_replaced(start_line, old, new);
+        // Lines that now follow others may start in or out of a multiline
+        // comment.
+        for row in self.rows.iter_mut().skip(start_line).take(new + 1) {
+            row.is_highlighted = false;
+        }
+        self.mark_dirty();
+        old - new
+    }
+
+    /// Removes repeated lines throughout the document, as
+    /// [`Document::dedup_lines`] does for a range.
+    pub fn dedup_all(&mut self, trimmed: bool) -> usize {
+        self.dedup_lines(0, self.len().saturating_sub(1), trimmed)
+    }
+
+    /// Rewrites the text so that it is safe to show on a terminal: ANSI
+    /// escape sequences, such as the colors in a log file, are removed, and
+    /// any other control char but tab is replaced by its picture (`␛` for
+    /// escape). Returns `false` if there was nothing to change or the
+    /// document is read-only.
+    ///
+    /// The document is otherwise kept as read; control chars are only drawn
+    /// as pictures, not changed, unless this is called.
+    pub fn sanitize(&mut self) -> bool {
+        if self.read_only {
+            return false;
+        }
+        let mut changed = false;
+        for row in &mut self.rows {
+            let sanitized = sanitize_line(&row.string);
+            if sanitized != row.string {
+                *row = Row::from(sanitized.as_str());
+                changed = true;
+            }
+        }
+        if changed {
+            self.mark_dirty();
+        }
+        changed
+    }
+
+    /// Converts the indentation of every line to `to`, counting a tab as
+    /// reaching the next multiple of `tab_width` columns. Returns `false` if
+    /// nothing changed or the document is read-only.
+    pub fn retab(&mut self, to: IndentStyle, tab_width: usize) -> bool {
+        self.retab_lines(0, self.len().saturating_sub(1), to, tab_width, false)
+    }
+
+    /// Converts the indentation of the lines from `start_line` to
+    /// `end_line` inclusive to `to`, keeping it as wide as it was with tabs
+    /// reaching the next multiple of `tab_width` columns. Indentation mixing
+    /// tabs and spaces ends up all tabs, padded with spaces where the width
+    /// isn't a whole number of tabs, or all spaces. With `all_tabs` set and
+    /// spaces as the target, tabs past the indentation are expanded too.
+    /// Returns `false` if nothing changed because the range is empty or out
+    /// of bounds, or the document is read-only.
+    pub fn retab_lines(
+        &mut self,
+        start_line: usize,
+        end_line: usize,
+        to: IndentStyle,
+        tab_width: usize,
+        all_tabs: bool,
+    ) -> bool {
+        let end_line = cmp::min(end_line.saturating_add(1), self.len());
+        if self.read_only || start_line >= end_line {
+            return false;
+        }
+
+        let tab_width = cmp::max(tab_width, 1);
+        let mut changed = false;
+        #[allow(clippy::indexing_slicing)]
+        for row in &mut self.rows[start_line..end_line] {
+            let retabbed = retab_line(&row.string, to, tab_width, all_tabs);
+            if retabbed != row.string {
+                *row = Row::from(retabbed.as_str());
+                changed = true;
+            }
+        }
+        if changed {
+            self.mark_dirty();
+        }
+        changed
+    }
+
+    /// Deletes the text covered by `sel`, joining what is left of its first
+    /// and last rows. Returns `false` if nothing was deleted because the
+    /// selection is empty or out of bounds, or the document is read-only.
+    #[allow(clippy::indexing_slicing, clippy::arithmetic_side_effects)]
+    pub fn delete_selection(&mut self, sel: &Selection) -> bool {
+        let (start, end) = (sel.start(), sel.end());
+        if self.read_only || sel.is_empty() || start.y >= self.rows.len() {
+            return false;
+        }
+        let last = self.rows.len() - 1;
+        let end =
+            cmp::min(end.clone(), Position::new(self.rows[last].len(), last));
+
+        let joined = format!(
+            "{}{}",
+            self.rows[start.y].slice(0, start.x),
+            self.rows[end.y].slice(end.x, usize::MAX)
+        );
+        self.rows
+            .splice(start.y..=end.y, iter::once(Row::from(joined.as_str())));
+        self.rows_replaced(start.y, end.y - start.y + 1, 1);
+
+        self.mark_dirty();
+        true
+    }
+
+    /// Deletes back from `at` to the start of the previous word, or joins the
+    /// row with the one above when `at` is at the start of it. Returns where
+    /// the cursor should go, which is `at` itself if nothing was deleted.
+    pub fn delete_word_backward(&mut self, at: &Position) -> Position {
+        let row_len = match self.rows.get(at.y) {
+            Some(row) => row.len(),
+            None => return at.clone(),
+        };
+        let start = if at.x > 0 {
+            #[allow(clippy::indexing_slicing)]
+            let x =
+                self.rows[at.y].previous_word_start(cmp::min(at.x, row_len));
+            Position::new(x, at.y)
+        } else if let Some(y) = at.y.checked_sub(1) {
+            Position::new(self.rows.get(y).map_or(0, Row::len), y)
+        } else {
+            return at.clone();
+        };
+
+        if self.delete_selection(&Selection::new(start.clone(), at.clone())) {
+            start
+        } else {
+            at.clone()
+        }
+    }
+
+    /// Deletes backwards from `at` like Backspace: a whole soft tab if `at`
+    /// ends an indentation of spaces that is a multiple of the soft tab
+    /// width, otherwise the grapheme before `at`, or the line break before
+    /// the row when `at` is at the start of it. Returns where the cursor
+    /// should go, which is `at` itself if nothing was deleted.
+    pub fn delete_indent_backward(&mut self, at: &Position) -> Position {
+        let row = self.rows.get(at.y);
+        let start = if at.x > 0 {
+            let spaces = row.map_or(false, |row| {
+                row.slice(0, at.x).chars().all(|c| c == ' ')
+            });
+            let x = match self.indent_style() {
+                IndentStyle::Spaces(width)
+                    if width > 0 && spaces && at.x % width == 0 =>
+                {
+                    at.x.saturating_sub(width)
+                },
+                _ => at.x.saturating_sub(1),
+            };
+            Position::new(x, at.y)
+        } else if let Some(y) = at.y.checked_sub(1) {
+            Position::new(self.rows.get(y).map_or(0, Row::len), y)
+        } else {
+            return at.clone();
+        };
+        // The virtual line past the end has no line break to delete.
+        if row.is_none() {
+            return start;
+        }
+
+        if self.delete_selection(&Selection::new(start.clone(), at.clone())) {
+            start
+        } else {
+            at.clone()
+        }
+    }
+
+    /// Deletes the grapheme at `at`, joining with the next row at the end of
+    /// a line. Returns `false` if nothing was deleted because the position is
+    /// at or past the end of the document or the document is read-only.
+    #[allow(clippy::arithmetic_side_effects, clippy::indexing_slicing)]
+    pub(crate) fn delete(&mut self, at: &Position) -> bool {
+        let len = self.rows.len();
+        if self.read_only || at.y >= len {
+            return false;
+        }
+        if at.x >= self.rows[at.y].len() && at.y + 1 == len {
+            return false;
+        }
+
+        self.mark_dirty();
 
         if at.x == self.rows[at.y].len() && at.y + 1 < len {
             let next_row = self.rows.remove(at.y + 1);
             let row = &mut self.rows[at.y];
             row.append(&next_row);
+            self.rows_replaced(at.y, 2, 1);
         } else {
             let row = &mut self.rows[at.y];
             row.delete(at.x);
         }
 
-        self.unhighlight_rows(at.y);
+        true
+    }
+
+    /// The nearest position to `pos` on an existing row: `y` no further than
+    /// the last row, or row 0 of an empty document where typing starts, and
+    /// `x` no further than the end of that row. This is the fixup for a
+    /// cursor after edits that may have shortened its row or removed rows.
+    pub fn clamp_cursor(&self, pos: &Position) -> Position {
+        let y = cmp::min(pos.y, self.len().saturating_sub(1));
+        let x = cmp::min(pos.x, self.row(y).map_or(0, Row::len));
+        Position::new(x, y)
     }
 
-    pub fn save(&mut self) -> Result<(), io::Error> {
-        if let Some(file_name) = &self.file_name {
-            let mut file = fs::File::create(file_name)?;
-            self.file_type = FileType::from(file_name.as_str());
+    /// Writes the document to its file. Fails with
+    /// [`DocumentError::NoFileName`] if it hasn't been given one yet.
+    ///
+    /// If the file is a symbolic link, the file it points to is written and
+    /// the link is kept, whether or not saves are atomic. A link to a file
+    /// that doesn't exist fails with [`DocumentError::BrokenSymlink`]
+    /// rather than creating the missing file.
+    pub fn save(&mut self) -> Result<(), DocumentError> {
+        if self.read_only {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "document is read-only",
+            )
+            .into());
+        }
+        let file_name =
+            self.file_name.clone().ok_or(DocumentError::NoFileName)?;
+
+        if self.save_options.backup && !self.backed_up {
+            if Path::new(&file_name).exists() {
+                fs::copy(&file_name, format!("{}~", file_name))?;
+            }
+            self.backed_up = true;
+        }
+
+        let target = write_target(&file_name)?;
+        if self.save_options.atomic {
+            self.write_atomic(&target)?;
+        } else {
+            self.write_rows(&mut fs::File::create(&target)?)?;
+        }
+
+        self.file_type = FileType::from(file_name.as_str());
+        self.disk_mtime = mtime(&file_name);
+        self.mark_saved();
+        Ok(())
+    }
 
-            for row in &mut self.rows {
+    fn write_rows(&self, file: &mut fs::File) -> Result<(), io::Error> {
+        if self.encoding.is_plain_utf8() {
+            let last = self.rows.len().saturating_sub(1);
+            for (index, row) in self.rows.iter().enumerate() {
                 file.write_all(row.as_bytes())?;
-                file.write_all(b"\n")?;
+                if index < last || self.final_newline() {
+                    file.write_all(b"\n")?;
+                }
             }
+        } else {
+            file.write_all(&self.encoding.encode(&self.contents())?)?;
+        }
+        file.sync_all()
+    }
+
+    /// The text `save()` writes, before encoding.
+    fn contents(&self) -> String {
+        let mut contents = self.lines().collect::<Vec<&str>>().join("\n");
+        if !self.rows.is_empty() && self.final_newline() {
+            contents.push('\n');
+        }
+        contents
+    }
+
+    /// Whether saving ends the last row with a newline.
+    fn final_newline(&self) -> bool {
+        match self.save_options.final_newline {
+            FinalNewline::Preserve => !self.missing_final_newline,
+            FinalNewline::Always => true,
+            FinalNewline::Never => false,
+        }
+    }
 
-            self.dirty = false;
+    /// Writes to a temporary sibling of `file_name` and renames it over the
+    /// target. If the rename fails the temporary file is discarded and the
+    /// target is written directly instead.
+    fn write_atomic(&self, file_name: &Path) -> Result<(), io::Error> {
+        let mut tmp_name = file_name.as_os_str().to_owned();
+        tmp_name.push(".hecto-tmp");
+        let mut tmp = fs::File::create(&tmp_name)?;
+        self.write_rows(&mut tmp)?;
+        if let Ok(metadata) = fs::metadata(file_name) {
+            tmp.set_permissions(metadata.permissions())?;
+        }
+        drop(tmp);
+
+        if let Err(rename_error) = fs::rename(&tmp_name, file_name) {
+            let _ = fs::remove_file(&tmp_name);
+            let mut file = fs::File::create(file_name).map_err(|error| {
+                io::Error::new(
+                    error.kind(),
+                    format!(
+                        "could not rename temporary file ({}) or write \
+                         directly ({})",
+                        rename_error, error
+                    ),
+                )
+            })?;
+            self.write_rows(&mut file)?;
         }
         Ok(())
     }
 
-    pub(crate) const fn is_dirty(&self) -> bool {
-        self.dirty
+    /// Whether the document has unsaved changes. Edits that restore the
+    /// text exactly as it was last read or written, such as typing a char
+    /// and deleting it again, leave it unmodified. The comparison is only
+    /// made once after each edit.
+    pub(crate) fn is_dirty(&self) -> bool {
+        if !self.dirty {
+            return false;
+        }
+        if let Some(differs) = self.differs_from_saved.get() {
+            return differs;
+        }
+        let differs = self.has_bom() != self.saved_bom
+            || !self.lines().eq(self.saved_lines.iter().map(String::as_str));
+        self.differs_from_saved.set(Some(differs));
+        differs
     }
 
     #[allow(clippy::indexing_slicing)]
@@ -137,9 +1292,16 @@ impl<'a> Document<'a> {
         at: &Position,
         direction: SearchDirection,
     ) -> Option<Position> {
-        if at.y >= self.rows.len() {
-            return None;
-        }
+        // Searching back from the line past the end starts at the end of the
+        // last row.
+        let last = self.rows.len().checked_sub(1)?;
+        let at = match direction {
+            SearchDirection::Forward if at.y > last => return None,
+            SearchDirection::Backward if at.y > last => {
+                Position::new(self.rows.get(last).map_or(0, Row::len), last)
+            },
+            _ => at.clone(),
+        };
 
         let mut position = Position::new(at.x, at.y);
         let (start, end) = match direction {
@@ -149,7 +1311,7 @@ impl<'a> Document<'a> {
 
         for _ in start..end {
             if let Some(row) = self.rows.get(position.y) {
-                if let Some(x) = row.find(&query, position.x, direction) {
+                if let Some(x) = row.find(query, position.x, direction) {
                     position.x = x;
                     return Some(position);
                 }
@@ -171,12 +1333,68 @@ impl<'a> Document<'a> {
         None
     }
 
+    /// Number of occurrences of `query` in the document, counting those that
+    /// overlap, as stepping through them with search does.
+    pub fn count_matches(&self, query: &str) -> usize {
+        self.matches(query).count()
+    }
+
+    /// Which occurrence of `query` starts at `at`, counting from 1, or
+    /// `None` if none does.
+    pub fn match_ordinal(&self, query: &str, at: &Position) -> Option<usize> {
+        let mut ordinal: usize = 0;
+        for position in self.matches(query) {
+            if position > *at {
+                break;
+            }
+            ordinal = ordinal.saturating_add(1);
+            if position == *at {
+                return Some(ordinal);
+            }
+        }
+        None
+    }
+
+    /// The start of every occurrence of `query`, in order.
+    fn matches<'a>(
+        &'a self,
+        query: &'a str,
+    ) -> impl Iterator<Item = Position> + 'a {
+        let first =
+            self.find(query, &Position::default(), SearchDirection::Forward);
+        iter::successors(first, move |previous| {
+            let next = Position::new(previous.x.saturating_add(1), previous.y);
+            self.find(query, &next, SearchDirection::Forward)
+        })
+    }
+
+    /// Marks every occurrence of `word` in the document as a match until
+    /// [`Document::clear_highlight_all`] is called. An active search takes
+    /// precedence while it lasts.
+    pub fn highlight_all(&mut self, word: &str) {
+        self.match_word = Some(word.to_string());
+        self.highlight(None, None);
+    }
+
+    pub fn clear_highlight_all(&mut self) {
+        self.match_word = None;
+    }
+
+    pub fn highlighted_all(&self) -> Option<&str> {
+        self.match_word.as_deref()
+    }
+
+    /// Highlights the rows up to and including `until`, marking matches of
+    /// the search `word` if there is one, or of the word set by
+    /// [`Document::highlight_all`] otherwise.
     pub(crate) fn highlight(
         &mut self,
         word: Option<&str>,
         until: Option<usize>,
     ) {
         let mut start_with_comment = false;
+        self.mark_word(word);
+        let word = self.marked_word.as_deref();
 
         let until = if let Some(until) = until {
             if until.saturating_add(1) < self.rows.len() {
@@ -188,25 +1406,405 @@ impl<'a> Document<'a> {
             self.rows.len()
         };
 
+        let mut diagnostics = &self.diagnostics[..];
         #[allow(clippy::indexing_slicing)]
-        for row in &mut self.rows[..until] {
+        for (y, row) in self.rows[..until].iter_mut().enumerate() {
+            let (on_row, below) = split_at_line(diagnostics, y);
+            diagnostics = below;
             start_with_comment = row.highlight(
-                &self.file_type.highlighting_options(),
+                self.file_type.highlighting_options(),
                 word,
+                on_row,
                 start_with_comment,
             );
         }
     }
 
-    pub(crate) fn unhighlight_rows(&mut self, start: usize) {
-        let start = start.saturating_sub(1);
+    /// Highlights the row at `index` again, and only it, marking `word` as
+    /// [`Document::highlight`] does. Whether the row starts in a multiline
+    /// comment is taken from the row above if that is highlighted, and
+    /// found by highlighting the rows above otherwise. Rows below aren't
+    /// touched, even if the row now ends in or out of a comment; the next
+    /// [`Document::highlight`] catches up with them.
+    pub fn highlight_row(&mut self, index: usize, word: Option<&str>) {
+        if index >= self.rows.len() {
+            return;
+        }
+        self.mark_word(word);
+
+        let opts = self.file_type.highlighting_options();
+        let start_with_comment = match index.checked_sub(1) {
+            Some(above) if opts.multiline_comment().is_some() => {
+                if self.row(above).map_or(false, |row| !row.is_highlighted) {
+                    self.highlight(word, Some(above));
+                }
+                self.row(above).map_or(false, Row::ends_in_comment)
+            },
+            _ => false,
+        };
+
+        let (diagnostics, _) = split_at_line(&self.diagnostics, index);
+        let word = self.marked_word.as_deref();
+        if let Some(row) = self.rows.get_mut(index) {
+            row.is_highlighted = false;
+            row.highlight(
+                self.file_type.highlighting_options(),
+                word,
+                diagnostics,
+                start_with_comment,
+            );
+        }
+    }
+
+    /// Highlights every row with its syntax alone, leaving out the word set
+    /// by [`Document::highlight_all`] and the diagnostics, and hands the
+    /// document to `f`. The rows are highlighted in full again the next
+    /// time they are drawn.
+    pub(crate) fn with_syntax_highlighting<T>(
+        &mut self,
+        f: impl FnOnce(&Self) -> T,
+    ) -> T {
+        let match_word = self.match_word.take();
+        let diagnostics = mem::take(&mut self.diagnostics);
+        self.unhighlight_rows(0);
+        self.highlight(None, None);
+        let result = f(self);
+        self.match_word = match_word;
+        self.diagnostics = diagnostics;
+        self.unhighlight_rows(0);
+        result
+    }
+
+    /// Makes `word`, or the word set by [`Document::highlight_all`] if there
+    /// is none, the one marked in the rows, re-highlighting them all if it
+    /// changed.
+    fn mark_word(&mut self, word: Option<&str>) {
+        let word = word.or(self.match_word.as_deref());
+        if word != self.marked_word.as_deref() {
+            self.marked_word = word.map(String::from);
+            self.unhighlight_rows(0);
+        }
+    }
+
+    /// Folds the lines from `start_line` to `end_line` inclusive, so that
+    /// only the first of them shows. Folds it overlaps or touches are
+    /// merged into it. Returns `false` if there was nothing to fold because
+    /// the range has fewer than two lines.
+    pub fn fold(&mut self, start_line: usize, end_line: usize) -> bool {
+        let end_line = cmp::min(end_line, self.rows.len().saturating_sub(1));
+        if start_line >= end_line {
+            return false;
+        }
+
+        let (mut first, mut last) = (start_line, end_line);
+        self.folds.retain(|&(fold_first, fold_last)| {
+            let apart = fold_last.saturating_add(1) < first
+                || fold_first > last.saturating_add(1);
+            if !apart {
+                first = cmp::min(first, fold_first);
+                last = cmp::max(last, fold_last);
+            }
+            apart
+        });
+        let index = self
+            .folds
+            .iter()
+            .position(|&(fold_first, _)| fold_first > first)
+            .unwrap_or(self.folds.len());
+        self.folds.insert(index, (first, last));
+        true
+    }
 
+    /// Unfolds the fold containing `line`. Returns `false` if there is none.
+    pub fn unfold(&mut self, line: usize) -> bool {
+        let count = self.folds.len();
+        self.folds
+            .retain(|&(first, last)| line < first || line > last);
+        self.folds.len() < count
+    }
+
+    /// The fold containing `line`, as `(first, last)`.
+    pub fn fold_at(&self, line: usize) -> Option<(usize, usize)> {
+        self.folds
+            .iter()
+            .find(|&&(first, last)| first <= line && line <= last)
+            .copied()
+    }
+
+    /// Whether `line` is folded away, being in a fold but not its first
+    /// line.
+    pub fn is_hidden(&self, line: usize) -> bool {
+        self.fold_at(line).map_or(false, |(first, _)| line > first)
+    }
+
+    /// The rows that aren't folded away, with their line numbers.
+    pub fn visible_rows(&self) -> impl Iterator<Item = (usize, &Row)> {
+        self.visible_rows_from(0)
+    }
+
+    /// The rows from `line` on that aren't folded away, with their line
+    /// numbers.
+    pub fn visible_rows_from(
+        &self,
+        line: usize,
+    ) -> impl Iterator<Item = (usize, &Row)> {
+        self.rows
+            .iter()
+            .enumerate()
+            .skip(line)
+            .filter(move |&(y, _)| !self.is_hidden(y))
+    }
+
+    /// The screen line `line` shows on when scrolled to the top, which is
+    /// `line` less the lines folded away above it. A hidden line maps to
+    /// the first line of its fold.
+    pub fn display_line(&self, line: usize) -> usize {
+        let hidden: usize = self
+            .folds
+            .iter()
+            .take_while(|&&(first, _)| first < line)
+            .map(|&(first, last)| cmp::min(last, line).saturating_sub(first))
+            .sum();
+        line.saturating_sub(hidden)
+    }
+
+    /// The line shown on screen line `display`, the inverse of
+    /// [`Document::display_line`].
+    pub fn line_at_display(&self, display: usize) -> usize {
+        let mut line = display;
+        for &(first, last) in &self.folds {
+            if first >= line {
+                break;
+            }
+            line = line.saturating_add(last.saturating_sub(first));
+        }
+        line
+    }
+
+    /// Keeps the folds in step with the `old` rows from `start` on having
+    /// been replaced by `new` rows. Folds that included replaced rows are
+    /// dropped.
+    fn rows_replaced(&mut self, start: usize, old: usize, new: usize) {
+        if old == new {
+            return;
+        }
+        let end = start.saturating_add(old);
+        self.folds
+            .retain(|&(first, last)| last < start || first >= end);
+        for (first, last) in &mut self.folds {
+            if *first >= end {
+                *first = first.saturating_add(new).saturating_sub(old);
+                *last = last.saturating_add(new).saturating_sub(old);
+            }
+        }
+    }
+
+    /// Replaces the diagnostics shown in the document. They are drawn over
+    /// the syntax highlighting of the columns they cover, and stay where
+    /// they are as the document is edited, until replaced again.
+    pub fn set_diagnostics(&mut self, mut diagnostics: Vec<Diagnostic>) {
+        diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+        self.diagnostics = diagnostics;
+        self.unhighlight_rows(0);
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// The syntax at `pos`, e.g. to tell whether it lies inside a string or
+    /// comment. Only reads highlighting that has already been computed, so
+    /// rows that [`Document::highlight`] hasn't reached yet give `None`. The
+    /// editor highlights every row up to the bottom of the screen before
+    /// drawing, which covers the cursor.
+    pub fn syntax_at(&self, pos: &Position) -> Option<highlighting::Type> {
+        self.row(pos.y)?.highlighting_at(pos.x)
+    }
+
+    /// Forgets the highlighting of the rows from `start` on, for changes
+    /// that affect how rows are marked rather than what they contain. Edits
+    /// don't need this: a row forgets its highlighting when its text
+    /// changes, and [`Document::highlight`] redoes the rows after it only if
+    /// they now start in or out of a multiline comment.
+    pub(crate) fn unhighlight_rows(&mut self, start: usize) {
         for row in self.rows.iter_mut().skip(start) {
             row.is_highlighted = false;
         }
     }
 }
 
+/// Splits `diagnostics`, sorted by line, into those on `line` and those
+/// below it, skipping any above it.
+fn split_at_line(
+    diagnostics: &[Diagnostic],
+    line: usize,
+) -> (&[Diagnostic], &[Diagnostic]) {
+    let above = diagnostics
+        .iter()
+        .take_while(|diagnostic| diagnostic.line < line)
+        .count();
+    let on_line = diagnostics
+        .iter()
+        .skip(above)
+        .take_while(|diagnostic| diagnostic.line == line)
+        .count();
+    let (_, rest) = diagnostics.split_at(above);
+    rest.split_at(on_line)
+}
+
+/// The file that saving `file_name` writes to: the file itself, or the one
+/// it links to if it is a symbolic link, so that the link survives.
+fn write_target(file_name: &str) -> Result<PathBuf, DocumentError> {
+    let is_symlink = fs::symlink_metadata(file_name)
+        .map_or(false, |metadata| metadata.file_type().is_symlink());
+    if !is_symlink {
+        return Ok(PathBuf::from(file_name));
+    }
+    fs::canonicalize(file_name).map_err(|error| {
+        if error.kind() == io::ErrorKind::NotFound {
+            DocumentError::BrokenSymlink(file_name.to_string())
+        } else {
+            error.into()
+        }
+    })
+}
+
+fn mtime(filename: &str) -> Option<SystemTime> {
+    fs::metadata(filename).and_then(|meta| meta.modified()).ok()
+}
+
+/// `line` with its indentation converted to `to` as described for
+/// [`Document::retab_lines`].
+#[allow(clippy::integer_division, clippy::arithmetic_side_effects)]
+fn retab_line(
+    line: &str,
+    to: IndentStyle,
+    tab_width: usize,
+    all_tabs: bool,
+) -> String {
+    let next_stop = |column: usize| (column / tab_width + 1) * tab_width;
+    let text = line.trim_start_matches(&[' ', '\t'][..]);
+    let indent = &line[..line.len() - text.len()];
+    let width = indent.chars().fold(0, |column, c| {
+        if c == '\t' {
+            next_stop(column)
+        } else {
+            column + 1
+        }
+    });
+
+    let mut result = match to {
+        IndentStyle::Tabs => format!(
+            "{}{}",
+            "\t".repeat(width / tab_width),
+            " ".repeat(width % tab_width)
+        ),
+        IndentStyle::Spaces(_) => " ".repeat(width),
+    };
+    if !all_tabs || to == IndentStyle::Tabs {
+        result.push_str(text);
+        return result;
+    }
+
+    let mut column = width;
+    for grapheme in text.graphemes(true) {
+        if grapheme == "\t" {
+            let stop = next_stop(column);
+            result.push_str(&" ".repeat(stop - column));
+            column = stop;
+        } else {
+            result.push_str(grapheme);
+            column += grapheme_width(grapheme);
+        }
+    }
+    result
+}
+
+/// `line` without ANSI escape sequences, and with its other control chars
+/// replaced by their pictures.
+fn sanitize_line(line: &str) -> String {
+    let mut sanitized = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\u{1b}' {
+            sanitized.push(control_picture(c).unwrap_or(c));
+            continue;
+        }
+        match chars.peek() {
+            // Control sequence: parameters and intermediates up to a final
+            // byte in `@`..=`~`.
+            Some('[') => {
+                chars.next();
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            },
+            // Operating system command, such as a window title or a
+            // hyperlink, ended by BEL or by ESC `\`.
+            Some(']') => {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    if c == '\u{7}' {
+                        break;
+                    }
+                    if c == '\u{1b}' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            },
+            // Two char escapes, like ESC `c` to reset the terminal.
+            Some(&next) if ('@'..='~').contains(&next) => {
+                chars.next();
+            },
+            _ => sanitized.push(control_picture(c).unwrap_or(c)),
+        }
+    }
+    sanitized
+}
+
+/// The rows of a listing of `dirname`: `../`, then the directories in it
+/// and then the other entries, each sorted by name. Entries whose names
+/// aren't valid Unicode are shown lossily.
+fn list_directory(dirname: &str) -> Result<Vec<Row>, io::Error> {
+    let mut entries: Vec<(bool, String)> = fs::read_dir(dirname)?
+        .filter_map(Result::ok)
+        .map(|entry| {
+            let is_dir = entry.path().is_dir();
+            (!is_dir, entry.file_name().to_string_lossy().into_owned())
+        })
+        .collect();
+    entries.sort();
+    Ok(iter::once(Row::from("../"))
+        .chain(entries.into_iter().map(|(is_file, name)| {
+            if is_file {
+                Row::from(name.as_str())
+            } else {
+                Row::from(format!("{}/", name).as_str())
+            }
+        }))
+        .collect())
+}
+
+/// The rows of `filename`, and whether it lacks a newline after the last
+/// one.
+fn read_rows(
+    filename: &str,
+    fallback: &'static Encoding,
+) -> Result<(Vec<Row>, bool, FileEncoding), io::Error> {
+    let (contents, encoding) =
+        FileEncoding::decode(&fs::read(filename)?, fallback)?;
+    let missing_final_newline =
+        !contents.is_empty() && !contents.ends_with('\n');
+    Ok((
+        contents.lines().map(Row::from).collect(),
+        missing_final_newline,
+        encoding,
+    ))
+}
+
 // impl From<&str> for Document {
 //     fn from(value: &str) -> Self {
 //         let mut rows = vec![];
@@ -218,3 +1816,243 @@ impl<'a> Document<'a> {
 //         Self { rows }
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Severity;
+
+    use std::{env, process};
+
+    fn document(text: &str) -> Document {
+        let mut document = Document::default();
+        document.insert_str(&Position::default(), text);
+        document
+    }
+
+    #[test]
+    fn block_insertion_lines_up_on_screen() {
+        let mut document = document("ab\tcd\n中文x\nz");
+        let sel =
+            BlockSelection::new(&Position::new(2, 0), &Position::new(4, 2));
+        assert!(document.insert_block(&sel, "|"));
+        assert_eq!(document.to_string(), "ab|\tcd\n中|文x\nz |");
+        assert!(!document.insert_block(&sel, "a\nb"));
+
+        let inserted =
+            BlockSelection::new(&Position::new(2, 0), &Position::new(3, 2));
+        assert!(document.delete_block(&inserted));
+        assert_eq!(document.to_string(), "ab\tcd\n中文x\nz ");
+    }
+
+    #[test]
+    fn empty_file_open_type_search_save() {
+        let path =
+            env::temp_dir().join(format!("hecto-empty-{}.txt", process::id()));
+        fs::write(&path, "").unwrap();
+        let file_name = path.to_str().unwrap();
+
+        let mut document = Document::open(file_name).unwrap();
+        assert!(document.is_empty());
+        let origin = Position::default();
+        assert_eq!(document.find("a", &origin, SearchDirection::Forward), None);
+        assert_eq!(
+            document.find("a", &origin, SearchDirection::Backward),
+            None
+        );
+        assert!(!document.delete(&origin));
+
+        assert!(document.insert(&origin, 'h'));
+        assert!(document.insert(&Position::new(1, 0), 'i'));
+        assert_eq!(
+            document.find("i", &origin, SearchDirection::Forward),
+            Some(Position::new(1, 0))
+        );
+        assert_eq!(
+            document.find("h", &Position::new(0, 1), SearchDirection::Backward),
+            Some(origin)
+        );
+
+        document.save().unwrap();
+        assert!(!document.is_dirty());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hi\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+    fn highlighted_rows(document: &Document) -> Vec<bool> {
+        document.rows.iter().map(|row| row.is_highlighted).collect()
+    }
+
+    #[test]
+    fn edits_only_rehighlight_rows_whose_comment_state_changed() {
+        let mut document = document("let a = 1;\nlet b = 2;\nlet c = 3;");
+        document.file_type = FileType::from("a.rs");
+        document.highlight(None, None);
+        assert_eq!(highlighted_rows(&document), vec![true, true, true]);
+
+        document.insert(&Position::new(10, 0), ' ');
+        assert_eq!(highlighted_rows(&document), vec![false, true, true]);
+        document.highlight(None, None);
+        assert_eq!(highlighted_rows(&document), vec![true, true, true]);
+
+        // Opening a comment changes how the rows below start, so they are
+        // redone even though their text is the same.
+        document.insert_str(&Position::new(0, 1), "/*");
+        document.highlight(None, None);
+        assert_eq!(
+            document.syntax_at(&Position::new(0, 2)),
+            Some(highlighting::Type::MultilineComment)
+        );
+    }
+
+    #[test]
+    fn duplicate_the_last_line() {
+        let mut document = document("a\nb");
+        assert_eq!(document.duplicate_line(1), Some(Position::new(0, 2)));
+        assert_eq!(document.to_string(), "a\nb\nb");
+        assert_eq!(document.duplicate_range(0, 2), Some(Position::new(0, 3)));
+        assert_eq!(document.to_string(), "a\nb\nb\na\nb\nb");
+        assert_eq!(document.duplicate_line(6), None);
+    }
+
+    #[test]
+    fn duplicate_an_empty_line() {
+        let mut document = document("a\n\nb");
+        assert_eq!(document.duplicate_line(1), Some(Position::new(0, 2)));
+        assert_eq!(document.to_string(), "a\n\n\nb");
+        assert_eq!(Document::default().duplicate_line(0), None);
+    }
+
+    #[test]
+    fn edits_that_restore_the_saved_text_leave_it_clean() {
+        let mut document = document("ab");
+        document.mark_saved();
+        assert!(!document.is_dirty());
+
+        document.insert(&Position::new(2, 0), 'c');
+        assert!(document.is_dirty());
+        assert!(document.delete(&Position::new(2, 0)));
+        assert!(!document.is_dirty());
+
+        assert!(document.insert(&Position::new(2, 0), '\n'));
+        assert!(document.is_dirty());
+    }
+
+    #[test]
+    fn toggling_the_byte_order_mark_back_leaves_it_clean() {
+        let mut document = document("ab");
+        document.mark_saved();
+        assert!(document.set_bom(true));
+        assert!(document.is_dirty());
+        assert!(document.set_bom(false));
+        assert!(!document.is_dirty());
+    }
+
+    #[test]
+    fn toggling_comments_on_blank_lines_changes_nothing() {
+        let mut document = document("fn a() {}\n\n  \nb();");
+        document.file_type = FileType::from("a.rs");
+        document.mark_saved();
+        assert!(!document.toggle_comment(1, 2));
+        assert!(!document.dirty);
+
+        assert!(document.toggle_comment(0, 3));
+        assert_eq!(document.to_string(), "// fn a() {}\n\n  \n// b();");
+        assert!(document.toggle_comment(0, 3));
+        assert_eq!(document.to_string(), "fn a() {}\n\n  \nb();");
+    }
+
+    #[test]
+    fn deleting_at_the_end_of_the_last_row_changes_nothing() {
+        let mut document = document("ab\ncd");
+        document.mark_saved();
+        assert!(!document.delete(&Position::new(2, 1)));
+        assert!(!document.dirty);
+        assert!(document.delete(&Position::new(2, 0)));
+        assert_eq!(document.to_string(), "abcd");
+    }
+
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!(
+            "hecto-save-{}-{}",
+            name,
+            process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.txt");
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn backup_is_made_on_the_first_save_only() {
+        let path = temp_file("backup", "one\n");
+        let file_name = path.to_str().unwrap();
+        let backup = format!("{}~", file_name);
+        let mut document = Document::open(file_name).unwrap();
+        document.set_save_options(SaveOptions {
+            backup: true,
+            ..SaveOptions::default()
+        });
+
+        document.insert_str(&Position::default(), "two ");
+        document.save().unwrap();
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "one\n");
+
+        document.insert_str(&Position::default(), "three ");
+        document.save().unwrap();
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "one\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "three two one\n");
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn atomic_save_replaces_the_file_and_keeps_its_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = temp_file("atomic", "old\n");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        let file_name = path.to_str().unwrap();
+        let mut document = Document::open(file_name).unwrap();
+        document.set_save_options(SaveOptions {
+            atomic: true,
+            ..SaveOptions::default()
+        });
+
+        document.insert_str(&Position::default(), "new ");
+        document.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new old\n");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        let dir = path.parent().unwrap();
+        let names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec!["a.txt"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn diagnostics_given_out_of_order_land_on_their_rows() {
+        let mut document = document("aa\nbb\ncc");
+        let diagnostic = |line, severity| Diagnostic {
+            line,
+            col_start: 0,
+            col_end: 1,
+            severity,
+            message: String::new(),
+        };
+        document.set_diagnostics(vec![
+            diagnostic(2, Severity::Warning),
+            diagnostic(0, Severity::Error),
+            diagnostic(2, Severity::Error),
+        ]);
+        document.highlight(None, None);
+        let first_span = |y| document.row(y).unwrap().highlight_spans()[0];
+        assert_eq!(first_span(0), (0, 1, highlighting::Type::Error));
+        assert_eq!(first_span(1), (0, 2, highlighting::Type::None));
+        assert_eq!(first_span(2), (0, 1, highlighting::Type::Error));
+    }
+}
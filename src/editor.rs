@@ -1,157 +1,669 @@
-use crate::{Document, Row, Terminal};
+use crate::{
+    cargo_diagnostics, highlighting,
+    row::{bg_string, truncate_to_width, RULER_COLOR},
+    search_files, Action, Buffer, BufferKind, BufferList, CommandRegistry,
+    Config, Document, DocumentError, FilePicker, IndentStyle, JumpList, KeyMap,
+    RecentFiles, Row, SaveOptions, Severity, Terminal, ViewState,
+};
 
 use anyhow::Result;
+use chrono::Local;
+use encoding_rs::Encoding;
 use termion::{color, event::Key};
+use unicode_width::UnicodeWidthStr;
 
 use std::{
-    env, io,
+    cmp,
+    convert::TryFrom,
+    fmt::Write,
+    io, mem,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
     time::{Duration, Instant},
 };
 
 const STATUS_FG_COLOR: color::Rgb = color::Rgb(63, 63, 63);
 const STATUS_BG_COLOR: color::Rgb = color::Rgb(239, 239, 239);
+const GUTTER_FG_COLOR: color::Rgb = color::Rgb(101, 123, 131);
+const CURRENT_LINE_NUMBER_COLOR: color::Rgb = color::Rgb(181, 137, 0);
+const DEFAULT_RULER: usize = 80;
+const CURRENT_LINE_COLOR: color::Rgb = color::Rgb(38, 38, 38);
 const VERSION: &str = env!("CARGO_PKG_VERSION");
-const QUIT_TIMES: u8 = 3;
+/// How many directories deep the file picker looks for files.
+const PICKER_DEPTH: usize = 8;
 
-pub struct Editor<'a> {
+#[allow(clippy::struct_excessive_bools)]
+pub struct Editor {
     should_quit: bool,
     terminal: Terminal,
     cursor_position: Position,
-    offset: Position,
-    document: Document<'a>,
+    view: ViewState,
+    document: Document,
     status_message: StatusMessage,
-    quit_times: u8,
     highlighted_word: Option<String>,
+    line_numbers: LineNumbers,
+    keymap: KeyMap,
+    commands: CommandRegistry,
+    /// Whether typing an opening bracket or quote also inserts its closer.
+    auto_pair: bool,
+    /// How documents opened in the editor are saved.
+    save_options: SaveOptions,
+    /// What Tab inserts in documents opened in the editor.
+    indent_style: IndentStyle,
+    /// Encoding of files that have no byte order mark and aren't UTF-8.
+    fallback_encoding: &'static Encoding,
+    /// Whether URLs are drawn as clickable OSC 8 hyperlinks.
+    hyperlinks: bool,
+    /// Whether spaces and tabs are drawn as visible glyphs.
+    show_whitespace: bool,
+    /// The display column the ruler shades, counted from 0, so that text
+    /// kept under that many columns stays clear of it.
+    ruler: usize,
+    show_ruler: bool,
+    /// Background the cursor's line is drawn on.
+    current_line_color: color::Rgb,
+    show_current_line: bool,
+    /// While searching, which match the cursor is on, if any, and how many
+    /// there are.
+    search_matches: Option<(Option<usize>, usize)>,
+    /// `strftime`-style format of the timestamps the editor inserts.
+    timestamp_format: String,
+    jump_list: JumpList,
+    /// The open buffers, among which the one being edited is lent out to
+    /// the fields above.
+    buffers: BufferList,
+    /// Files opened or saved lately, unless remembering them is turned off.
+    recent_files: Option<RecentFiles>,
+    /// The actions performed since recording a macro started, while it is
+    /// being recorded.
+    recording: Option<Vec<Action>>,
+    recorded_macro: Vec<Action>,
+    /// Set when an action flashes because it failed, or a prompt is
+    /// cancelled, so that a macro replay stops there.
+    interrupted: bool,
 }
 
-impl<'a> Editor<'a> {
-    pub fn new() -> Result<Self> {
-        let args: Vec<String> = env::args().collect();
+impl Editor {
+    /// Opens `file_name`, if any, with the cursor at `position` clamped to
+    /// the document.
+    pub fn new(file_name: Option<&str>, position: &Position) -> Result<Self> {
         let mut initial_status =
             String::from("HELP: Ctrl-F = find | Ctrl-S = save | Ctrl-Q = quit");
 
-        let document = if let Some(file_name) = args.get(1) {
-            let doc = Document::open(file_name);
-            if let Ok(doc) = doc {
-                doc
-            } else {
-                initial_status =
-                    format!("ERR: Could not open file: {}", file_name);
-                Document::default()
+        let config = Config::load().unwrap_or_else(|error| {
+            initial_status = format!("ERR: {}", error);
+            Config::default()
+        });
+        config.register_filetypes();
+        let mut keymap = KeyMap::default();
+        config.apply_keys(&mut keymap);
+        let save_options = SaveOptions {
+            backup: config.editor.backup,
+            atomic: config.editor.atomic_save,
+            final_newline: config.editor.final_newline,
+        };
+
+        let mut document = if let Some(file_name) = file_name {
+            match Document::open_with_encoding(
+                file_name,
+                config.editor.fallback_encoding(),
+            ) {
+                Ok(doc) => doc,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                    initial_status = format!("New file: {}", file_name);
+                    let mut doc = Document::new_scratch();
+                    doc.set_file_name(file_name);
+                    doc
+                },
+                Err(error) => {
+                    initial_status =
+                        format!("ERR: Could not open {}: {}", file_name, error);
+                    Document::new_scratch()
+                },
             }
         } else {
-            Document::default()
+            Document::new_scratch()
         };
+        document.set_save_options(save_options);
+        document.set_indent_style(config.editor.indent);
 
-        Ok(Self {
+        let recent_files = if config.editor.recent_files {
+            config
+                .editor
+                .recent_files_path
+                .clone()
+                .or_else(RecentFiles::default_state_file)
+                .map(RecentFiles::load)
+        } else {
+            None
+        };
+
+        let mut terminal = Terminal::new()?;
+        terminal.set_bell(config.editor.bell);
+        let mut view = ViewState::new(*terminal.size());
+        view.scroll_margin = config.editor.scroll_margin;
+
+        let mut editor = Self {
             should_quit: false,
-            terminal: Terminal::new()?,
+            terminal,
             cursor_position: Position::default(),
-            offset: Position::default(),
+            view,
             document,
             status_message: StatusMessage::new(initial_status),
-            quit_times: QUIT_TIMES,
             highlighted_word: None,
-        })
+            line_numbers: LineNumbers::Off,
+            keymap,
+            commands: CommandRegistry::default(),
+            auto_pair: config.editor.auto_pair,
+            save_options,
+            indent_style: config.editor.indent,
+            fallback_encoding: config.editor.fallback_encoding(),
+            hyperlinks: config.editor.hyperlinks,
+            show_whitespace: false,
+            ruler: config.editor.ruler.unwrap_or(DEFAULT_RULER),
+            show_ruler: config.editor.ruler.is_some(),
+            current_line_color: config
+                .editor
+                .current_line_color()
+                .unwrap_or(CURRENT_LINE_COLOR),
+            show_current_line: config.editor.current_line,
+            search_matches: None,
+            timestamp_format: config.editor.timestamp_format.clone(),
+            jump_list: JumpList::default(),
+            buffers: BufferList::default(),
+            recent_files,
+            recording: None,
+            recorded_macro: Vec::new(),
+            interrupted: false,
+        };
+        editor.remember_file();
+
+        let position = editor.document.clamp_cursor(position);
+        editor.jump_to(position);
+
+        Ok(editor)
     }
 
     pub fn run(&mut self) {
         loop {
             if let Err(error) = self.refresh_screen() {
-                die(error);
+                die(&error);
             }
             if self.should_quit {
                 break;
             }
             if let Err(error) = self.process_keypress() {
-                die(error);
+                die(&error);
             }
         }
     }
 
     fn refresh_screen(&mut self) -> Result<(), io::Error> {
-        Terminal::cursor_hide();
-        Terminal::cursor_position(&Position::default());
+        self.terminal.cursor_hide();
+        self.terminal.cursor_position(&Position::default());
 
         if self.should_quit {
-            Terminal::clear_screen();
-            println!("{}Goodbye.\r", color::Fg(color::Red));
+            self.terminal.clear_screen();
+            self.terminal
+                .write_buf(&format!("{}Goodbye.\r\n", color::Fg(color::Red)));
+        } else if !self.terminal.size().is_usable() {
+            // Too small to draw the document in, so just say so, as far as
+            // it fits.
+            let mut message = String::from("Terminal too small.");
+            message.truncate(self.terminal.size().width as usize);
+            self.terminal.clear_screen();
+            self.terminal.write_buf(&message);
+            self.terminal.cursor_position(&Position::default());
         } else {
             self.document.highlight(
                 self.highlighted_word.as_deref(),
-                Some(
-                    self.offset
-                        .y
-                        .saturating_add(self.terminal.size().height as usize),
-                ),
+                Some(self.document.line_at_display(
+                    self.view.offset.y.saturating_add(self.view.height()),
+                )),
             );
             self.draw_rows();
             self.draw_status_bar();
             self.draw_message_bar();
-            Terminal::cursor_position(
-                &self.cursor_position.with_offset(&self.offset),
-            );
+            let mut position =
+                self.display_position().with_offset(&self.view.offset);
+            position.x = position.x.saturating_add(self.gutter_width());
+            self.terminal.cursor_position(&position);
         }
 
-        Terminal::cursor_show();
-        Terminal::flush()
+        self.terminal.cursor_show();
+        self.terminal.flush()
     }
 
     fn save(&mut self) {
-        if self.document.file_name.is_none() {
-            let new_name = match self.prompt("Save as: ") {
-                Ok(s) => s,
-                Err(_) => None,
-            };
+        if self.document.is_read_only() {
+            self.warn_if_read_only();
+            return;
+        }
+        if let Ok(true) = self.document.externally_modified() {
+            let answer = self
+                .prompt("File changed on disk. Overwrite? (y/N): ")
+                .unwrap_or_default();
+            if answer.as_deref() != Some("y") {
+                self.status_message = StatusMessage::from("Save aborted.");
+                return;
+            }
+        }
 
-            if new_name.is_none() {
+        let mut result = self.document.save();
+        if let Err(DocumentError::NoFileName) = result {
+            let new_name = self.prompt("Save as: ").unwrap_or_default();
+
+            if let Some(new_name) = new_name {
+                self.document.set_file_name(&new_name);
+            } else {
                 self.status_message = StatusMessage::from("Save aborted.");
                 return;
             }
-            self.document.file_name = new_name;
+            result = self.document.save();
+        }
+
+        self.status_message = match result {
+            Ok(()) => {
+                self.remember_file();
+                StatusMessage::from("File saved successfully.")
+            },
+            Err(error) => {
+                StatusMessage::new(format!("ERR: Could not save: {}", error))
+            },
+        };
+    }
+
+    fn reload(&mut self) {
+        let mut result = self.document.reload(false);
+        if let Err(DocumentError::Dirty) = result {
+            let answer = self
+                .prompt("Discard unsaved changes and reload? (y/N): ")
+                .unwrap_or_default();
+            if answer.as_deref() != Some("y") {
+                self.status_message = StatusMessage::from("Reload aborted.");
+                return;
+            }
+            result = self.document.reload(true);
+        }
+
+        self.status_message = match result {
+            Ok(()) => StatusMessage::from("File reloaded."),
+            Err(error) => {
+                StatusMessage::new(format!("ERR: Could not reload: {}", error))
+            },
+        };
+        self.cursor_position =
+            self.document.clamp_cursor(&self.cursor_position);
+    }
+
+    fn open_file(&mut self) {
+        self.open_picked(&FilePicker::new(Path::new("."), PICKER_DEPTH));
+    }
+
+    fn open_recent(&mut self) {
+        let files = self
+            .recent_files
+            .as_ref()
+            .map(RecentFiles::recent_files)
+            .unwrap_or_default();
+        if files.is_empty() {
+            self.status_message = StatusMessage::from("No recent files.");
+            return;
+        }
+        self.open_picked(&FilePicker::from_files(files));
+    }
+
+    /// Opens a file chosen from `picker`.
+    fn open_picked(&mut self, picker: &FilePicker) {
+        let path = if let Some(path) = self.pick_file(picker).unwrap_or(None) {
+            path
+        } else {
+            self.status_message = StatusMessage::from("Open aborted.");
+            return;
+        };
+        self.open_path(&path, Position::default());
+    }
+
+    /// Opens the file at `path` and puts the cursor at `at`. A file that is
+    /// already open is switched to. Others open in a new buffer, unless the
+    /// document is a directory listing or an untouched scratch buffer, which
+    /// they replace. Gives whether the file could be opened.
+    fn open_path(&mut self, path: &Path, at: Position) -> bool {
+        let holds_path = |document: &Document| {
+            document
+                .file_name
+                .as_deref()
+                .map_or(false, |file_name| Path::new(file_name) == path)
+        };
+        if !holds_path(&self.document) {
+            if let Some(index) = self.buffers.find(path) {
+                self.switch_buffer(index);
+            }
+        }
+        if holds_path(&self.document) {
+            let at = self.document.clamp_cursor(&at);
+            self.jump_to(at);
+            return true;
+        }
+        let file_name = path.to_string_lossy();
+
+        match Document::open_with_encoding(&file_name, self.fallback_encoding) {
+            Ok(mut document) => {
+                document.set_save_options(self.save_options);
+                document.set_indent_style(self.indent_style);
+                let replaceable = self.document.kind() == BufferKind::Directory
+                    || (self.document.file_name.is_none()
+                        && self.document.is_empty()
+                        && !self.document.is_dirty());
+                if !replaceable {
+                    let index = self.buffers.add();
+                    self.switch_buffer(index);
+                }
+                self.document = document;
+                self.highlighted_word = None;
+                self.jump_list.clear();
+                self.view.offset = Position::default();
+                self.jump_to(at);
+                self.status_message = StatusMessage::from("");
+                self.remember_file();
+                true
+            },
+            Err(error) => {
+                self.status_message = StatusMessage::new(format!(
+                    "ERR: Could not open {}: {}",
+                    file_name, error
+                ));
+                false
+            },
+        }
+    }
+
+    /// Makes the buffer at `index` the one being edited, keeping the
+    /// current one, cursor and view included, in the buffer list.
+    fn switch_buffer(&mut self, index: usize) {
+        let current = Buffer {
+            document: mem::take(&mut self.document),
+            cursor: mem::take(&mut self.cursor_position),
+            offset: mem::take(&mut self.view.offset),
+        };
+        let buffer = self.buffers.switch(index, current);
+        self.show_buffer(buffer);
+    }
+
+    fn show_buffer(&mut self, buffer: Buffer) {
+        self.document = buffer.document;
+        self.cursor_position = buffer.cursor;
+        self.view.offset = buffer.offset;
+        self.highlighted_word = None;
+        self.jump_list.clear();
+        self.scroll();
+    }
+
+    /// Closes the current buffer, asking first if it has unsaved changes,
+    /// and switches to the one before it. Closing the last buffer leaves an
+    /// empty one.
+    fn close_buffer(&mut self) {
+        if self.document.is_dirty() {
+            let answer = self
+                .prompt("Unsaved changes will be lost. Close anyway? (y/N): ")
+                .unwrap_or_default();
+            if answer.as_deref() != Some("y") {
+                self.status_message = StatusMessage::from("Close aborted.");
+                return;
+            }
         }
+        let buffer = self.buffers.close_active().unwrap_or_else(|| {
+            let mut document = Document::new_scratch();
+            document.set_save_options(self.save_options);
+            document.set_indent_style(self.indent_style);
+            Buffer {
+                document,
+                ..Buffer::default()
+            }
+        });
+        self.show_buffer(buffer);
+        self.status_message = StatusMessage::from("");
+    }
+
+    /// Opens the entry under the cursor in a directory listing, which may
+    /// be another directory.
+    fn open_entry(&mut self) {
+        if let Some(path) = self.document.entry_at(self.cursor_position.y) {
+            self.open_path(&path, Position::default());
+        }
+    }
 
-        if self.document.save().is_ok() {
+    /// Finds a query in all the files under the working directory, lets
+    /// the user pick one of the lines it is on and opens the file there.
+    fn project_search(&mut self) {
+        let query = match self.prompt("Search files: ").unwrap_or(None) {
+            Some(query) if !query.is_empty() => query,
+            _ => {
+                self.status_message = StatusMessage::from("Search aborted.");
+                return;
+            },
+        };
+        let hits = search_files(Path::new("."), &query, PICKER_DEPTH);
+        if hits.is_empty() {
             self.status_message =
-                StatusMessage::from("File saved successfully.");
+                StatusMessage::new(format!("No files contain {}.", query));
+            return;
+        }
+
+        let hit = self
+            .pick(
+                "Hits",
+                |filter| {
+                    hits.iter()
+                        .filter(|(path, _, text)| {
+                            path.to_string_lossy().contains(filter)
+                                || text.contains(filter)
+                        })
+                        .cloned()
+                        .collect()
+                },
+                |(path, line, text)| {
+                    format!("{}:{}: {}", path.display(), line + 1, text.trim())
+                },
+            )
+            .unwrap_or(None);
+        let (path, y, text) = if let Some(hit) = hit {
+            hit
+        } else {
+            self.status_message = StatusMessage::from("Search aborted.");
+            return;
+        };
+        let x = Row::from(text.as_str())
+            .find(&query, 0, SearchDirection::Forward)
+            .unwrap_or(0);
+        self.open_path(&path, Position { x, y });
+    }
+
+    /// Lets the user narrow down the files of `picker` by typing, move
+    /// through the matches with Tab or the arrow keys, and pick one with
+    /// Enter.
+    fn pick_file(
+        &mut self,
+        picker: &FilePicker,
+    ) -> Result<Option<PathBuf>, io::Error> {
+        self.pick(
+            "Open",
+            |query| picker.matches(query),
+            |path| path.display().to_string(),
+        )
+    }
+
+    /// Lets the user pick one of the commands by name and performs it.
+    fn command_palette(&mut self) {
+        let commands = self.commands.clone();
+        let command = self
+            .pick(
+                "Command",
+                |query| commands.matches(query),
+                |command| command.name.to_string(),
+            )
+            .unwrap_or(None);
+        if let Some(command) = command {
+            self.perform(command.action);
+        }
+    }
+
+    /// Lets the user narrow down what `matches` gives for the query they
+    /// type, move through the matches with Tab or the arrow keys, and pick
+    /// one with Enter. `label` and `display` say what is being picked and
+    /// how to show each match in the status bar.
+    fn pick<T, M, D>(
+        &mut self,
+        label: &str,
+        matches: M,
+        display: D,
+    ) -> Result<Option<T>, io::Error>
+    where
+        M: Fn(&str) -> Vec<T>,
+        D: Fn(&T) -> String,
+    {
+        let mut query = String::new();
+        let mut selected: usize = 0;
+
+        loop {
+            let matches = matches(&query);
+            selected = cmp::min(selected, matches.len().saturating_sub(1));
+            let listing = matches
+                .iter()
+                .enumerate()
+                .skip(selected)
+                .map(|(index, item)| {
+                    if index == selected {
+                        format!("[{}]", display(item))
+                    } else {
+                        display(item)
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join(" ");
+            self.status_message = StatusMessage::new(format!(
+                "{}: {} | {}",
+                label, query, listing
+            ));
+            self.refresh_screen()?;
+
+            match self.terminal.read_key()? {
+                Key::Backspace => {
+                    query.pop();
+                    selected = 0;
+                },
+                Key::Char('\n') => {
+                    self.status_message = StatusMessage::from("");
+                    return Ok(matches.into_iter().nth(selected));
+                },
+                Key::Char('\t') | Key::Down | Key::Right => {
+                    selected = selected.saturating_add(1);
+                },
+                Key::BackTab | Key::Up | Key::Left => {
+                    selected = selected.saturating_sub(1);
+                },
+                Key::Char(c) if !c.is_control() => {
+                    query.push(c);
+                    selected = 0;
+                },
+                Key::Esc => {
+                    self.status_message = StatusMessage::from("");
+                    self.interrupted = true;
+                    return Ok(None);
+                },
+                _ => (),
+            }
+        }
+    }
+
+    /// Adds the document's file to the recent files, if they are kept.
+    /// Failing to record it isn't worth interrupting the user for.
+    fn remember_file(&mut self) {
+        if self.document.kind() == BufferKind::Directory {
+            return;
+        }
+        if let (Some(recent_files), Some(file_name)) =
+            (&mut self.recent_files, &self.document.file_name)
+        {
+            let _ = recent_files.push(Path::new(file_name));
+        }
+    }
+
+    /// Moves to a position from the jump list, which edits since it was
+    /// recorded may have left out of bounds.
+    fn retrace_jump(&mut self, position: Option<Position>) {
+        if let Some(position) = position {
+            let position = self.document.clamp_cursor(&position);
+            self.jump_to(position);
         } else {
-            self.status_message = StatusMessage::from("Error writing file!");
+            self.status_message = StatusMessage::from("No more jumps.");
+            self.flash();
         }
     }
 
     fn search(&mut self) {
         let old_position = self.cursor_position.clone();
         let mut direction = SearchDirection::Forward;
+        // Counting scans the whole document, so it is only redone when the
+        // query changes, not when moving between matches.
+        let mut count: Option<(String, usize)> = None;
         let query = self
             .prompt_with(
                 "Search (ESC to cancel, Arrrows to navigate): ",
                 |editor, key, query| {
                     let mut moved = false;
-                    match key {
+                    let from = match key {
                         Key::Right | Key::Down => {
                             direction = SearchDirection::Forward;
                             editor.move_cursor(Key::Right);
                             moved = true;
+                            editor.cursor_position.clone()
                         },
                         Key::Left | Key::Up => {
-                            direction = SearchDirection::Backward
+                            direction = SearchDirection::Backward;
+                            editor.cursor_position.clone()
                         },
-                        _ => direction = SearchDirection::Forward,
-                    }
+                        // Editing the query searches again from where the
+                        // search started, so that deleting chars goes back
+                        // to the match found before typing them.
+                        _ => {
+                            direction = SearchDirection::Forward;
+                            old_position.clone()
+                        },
+                    };
 
-                    if let Some(position) = editor.document.find(
-                        &query,
-                        &editor.cursor_position,
-                        direction,
-                    ) {
-                        editor.cursor_position = position;
-                        editor.scroll();
-                    } else if moved {
-                        editor.move_cursor(Key::Left);
+                    if let Some(position) =
+                        editor.document.find(query, &from, direction)
+                    {
+                        editor.jump_to(position);
+                    } else if query.is_empty() {
+                        editor.jump_to(old_position.clone());
+                    } else {
+                        if moved {
+                            editor.move_cursor(Key::Left);
+                        }
+                        editor.flash();
                     }
 
                     editor.highlighted_word = Some(query.clone());
                     // editor.document.highlight(Some(query));
+
+                    let total = match &count {
+                        Some((counted, total)) if counted == query => *total,
+                        _ => {
+                            let total = editor.document.count_matches(query);
+                            count = Some((query.clone(), total));
+                            total
+                        },
+                    };
+                    let ordinal = editor
+                        .document
+                        .match_ordinal(query, &editor.cursor_position);
+                    editor.search_matches = if query.is_empty() {
+                        None
+                    } else {
+                        Some((ordinal, total))
+                    };
                 },
             )
             .unwrap_or(None);
@@ -159,49 +671,574 @@ impl<'a> Editor<'a> {
         if query.is_none() {
             self.cursor_position = old_position;
             self.scroll();
+        } else if self.cursor_position != old_position {
+            self.jump_list.push(old_position);
         }
 
         self.highlighted_word = None;
+        self.search_matches = None;
         // self.document.highlight(None);
     }
 
+    /// Unfolds the fold at the cursor, or folds the block of lines below
+    /// the cursor's line that are indented further than it.
+    fn toggle_fold(&mut self) {
+        let y = self.cursor_position.y;
+        if self.document.unfold(y) {
+            return;
+        }
+        let indentation = match self.document.row(y) {
+            Some(row) if !row.is_empty() => row.indentation(),
+            _ => return,
+        };
+        let last = self
+            .document
+            .visible_rows_from(y.saturating_add(1))
+            .take_while(|(_, row)| {
+                row.string.trim().is_empty() || row.indentation() > indentation
+            })
+            .filter(|(_, row)| !row.string.trim().is_empty())
+            .last()
+            .map(|(last, _)| last);
+        if !last.map_or(false, |last| self.document.fold(y, last)) {
+            self.status_message = StatusMessage::from("Nothing to fold here.");
+        }
+    }
+
+    /// Comments out the cursor's line, or uncomments it, keeping the cursor
+    /// on the same text.
+    fn toggle_comment(&mut self) {
+        let Position { x, y } = self.cursor_position;
+        let row_len = |doc: &Document| doc.row(y).map_or(0, Row::len);
+        let old_len = row_len(&self.document);
+
+        if self.document.toggle_comment(y, y) {
+            let new_len = row_len(&self.document);
+            let x = if new_len >= old_len {
+                x.saturating_add(new_len.saturating_sub(old_len))
+            } else {
+                x.saturating_sub(old_len.saturating_sub(new_len))
+            };
+            self.cursor_position = Position::new(cmp::min(x, new_len), y);
+        } else if self.document.is_read_only() {
+            self.warn_if_read_only();
+        } else {
+            self.status_message =
+                StatusMessage::from("No line comments for this file type.");
+        }
+    }
+
+    /// Inserts the current local time at the cursor, written in the
+    /// configured format.
+    fn insert_timestamp(&mut self) {
+        if self.document.is_read_only() {
+            self.warn_if_read_only();
+            return;
+        }
+        let timestamp = Local::now().format(&self.timestamp_format).to_string();
+        self.cursor_position =
+            self.document.insert_str(&self.cursor_position, &timestamp);
+    }
+
+    /// Strips escape sequences and other control chars out of the document,
+    /// as left in log files by programs that color their output.
+    fn sanitize(&mut self) {
+        if self.document.sanitize() {
+            self.cursor_position =
+                self.document.clamp_cursor(&self.cursor_position);
+            self.status_message =
+                StatusMessage::from("Removed control characters.");
+        } else if self.document.is_read_only() {
+            self.warn_if_read_only();
+        } else {
+            self.status_message =
+                StatusMessage::from("No control characters to remove.");
+        }
+    }
+
+    /// Converts the indentation of the document to the indent style, asking
+    /// how wide its tabs are meant to be. That defaults to the document's
+    /// tab width.
+    fn retab(&mut self) {
+        if self.document.is_read_only() {
+            self.warn_if_read_only();
+            return;
+        }
+        let default = self.document.tab_width();
+        let answer = self
+            .prompt(&format!("Tab width? ({}): ", default))
+            .unwrap_or_default();
+        let tab_width = match answer.map(|answer| answer.parse::<usize>()) {
+            None => default,
+            Some(Ok(width)) if width > 0 => width,
+            Some(_) => {
+                self.status_message = StatusMessage::from("Not a width.");
+                return;
+            },
+        };
+        self.status_message =
+            if self.document.retab(self.document.indent_style(), tab_width) {
+                self.cursor_position =
+                    self.document.clamp_cursor(&self.cursor_position);
+                StatusMessage::from("Indentation converted.")
+            } else {
+                StatusMessage::from("Indentation already consistent.")
+            };
+    }
+
+    /// Removes lines that repeat the line before them, like `uniq`.
+    fn dedup_lines(&mut self) {
+        let removed = self.document.dedup_all(false);
+        if removed > 0 {
+            self.cursor_position =
+                self.document.clamp_cursor(&self.cursor_position);
+            self.status_message = StatusMessage::new(format!(
+                "Removed {} duplicate line{}.",
+                removed,
+                if removed == 1 { "" } else { "s" }
+            ));
+        } else if self.document.is_read_only() {
+            self.warn_if_read_only();
+        } else {
+            self.status_message =
+                StatusMessage::from("No duplicate lines to remove.");
+        }
+    }
+
+    /// Adds a byte order mark to the file on saving, or drops the one it
+    /// has.
+    fn toggle_bom(&mut self) {
+        let bom = !self.document.has_bom();
+        if self.document.set_bom(bom) {
+            self.status_message = StatusMessage::from(if bom {
+                "Saving adds a byte order mark."
+            } else {
+                "Saving drops the byte order mark."
+            });
+        } else if self.document.is_read_only() {
+            self.warn_if_read_only();
+        } else {
+            self.status_message = StatusMessage::from(
+                "Only UTF-8 files can gain or drop a byte order mark.",
+            );
+        }
+    }
+
+    /// Marks every occurrence of the word under the cursor, or clears the
+    /// marks if there already are some.
+    fn toggle_highlight_all(&mut self) {
+        if self.document.highlighted_all().is_some() {
+            self.document.clear_highlight_all();
+            return;
+        }
+        let Position { x, y } = self.cursor_position;
+        let word = self
+            .document
+            .row(y)
+            .and_then(|row| row.word_at(x))
+            .map(String::from);
+        if let Some(word) = word {
+            self.document.highlight_all(&word);
+        } else {
+            self.status_message = StatusMessage::from("No word under cursor.");
+        }
+    }
+
+    /// Moves the cursor's line up or down past its neighbour, taking the
+    /// cursor along.
+    fn move_line(&mut self, direction: SearchDirection) {
+        let Position { x, y } = self.cursor_position;
+        let (moved, new_y) = match direction {
+            SearchDirection::Backward => {
+                (self.document.move_line_up(y), y.saturating_sub(1))
+            },
+            SearchDirection::Forward => {
+                (self.document.move_line_down(y), y.saturating_add(1))
+            },
+        };
+        if moved {
+            self.cursor_position = Position::new(x, new_y);
+        } else {
+            self.warn_if_read_only();
+        }
+    }
+
+    /// Moves the cursor to the start of the text on the next line, or the
+    /// previous one unless `forward`, indented as much as the cursor's.
+    fn move_to_indent(&mut self, forward: bool) {
+        let y = self.cursor_position.y;
+        let indent = self.document.row(y).map_or(0, Row::indentation);
+        match self.document.next_line_at_indent(y, indent, forward) {
+            Some(line) => self.jump_to(Position::new(indent, line)),
+            None => self.flash(),
+        }
+    }
+
+    /// Duplicates the cursor's line and moves the cursor onto the copy.
+    fn duplicate_line(&mut self) {
+        let x = self.cursor_position.x;
+        match self.document.duplicate_line(self.cursor_position.y) {
+            Some(copy) => self.cursor_position = Position::new(x, copy.y),
+            None => self.warn_if_read_only(),
+        }
+    }
+
+    /// Starts recording a macro, or stops and keeps the one being recorded.
+    fn toggle_recording(&mut self) {
+        if let Some(actions) = self.recording.take() {
+            self.status_message = StatusMessage::new(format!(
+                "Recorded a macro of {} actions.",
+                actions.len()
+            ));
+            self.recorded_macro = actions;
+        } else {
+            self.recording = Some(Vec::new());
+            self.status_message = StatusMessage::from("Recording a macro.");
+        }
+    }
+
+    /// Asks how many times to replay the recorded macro, once by default,
+    /// and replays it.
+    fn replay_macro(&mut self) {
+        if self.recorded_macro.is_empty() {
+            self.status_message = StatusMessage::from("No macro recorded.");
+            return;
+        }
+        let answer = self
+            .prompt("Replay macro how many times? (1): ")
+            .unwrap_or_default();
+        let count = match answer.map(|answer| answer.parse::<usize>()) {
+            None => 1,
+            Some(Ok(count)) => count,
+            Some(Err(_)) => {
+                self.status_message = StatusMessage::from("Not a number.");
+                return;
+            },
+        };
+        let actions = self.recorded_macro.clone();
+        self.replay(&actions, count);
+    }
+
+    /// Performs `actions` in order, `count` times over. Each action works
+    /// from wherever the ones before it left the cursor, as it did when
+    /// recorded. The replay stops early at an action that fails with a
+    /// flash, or at a prompt the user cancels.
+    fn replay(&mut self, actions: &[Action], count: usize) {
+        self.interrupted = false;
+        for _ in 0..count {
+            for &action in actions {
+                if self.should_quit || self.interrupted {
+                    return;
+                }
+                self.perform(action);
+                self.scroll();
+            }
+        }
+    }
+
     fn process_keypress(&mut self) -> Result<(), io::Error> {
-        let pressed_key = Terminal::read_key()?;
-
-        match pressed_key {
-            Key::Ctrl('q') => {
-                if self.quit_times > 0 && self.document.is_dirty() {
-                    self.status_message = StatusMessage::new(format!("WARNING! File has unsaved changes. Press Ctrl-Q {} more times to quit.", self.quit_times));
-                    // self.quit_times -= 1;
-                    self.quit_times = self.quit_times.saturating_sub(1);
-                    return Ok(());
+        let pressed_key = self.terminal.read_key()?;
+        let action = self.keymap.action(pressed_key);
+
+        if let Some(action) = action {
+            // Prompts read keys of their own, so what is typed into one,
+            // such as a search query, isn't recorded: replaying the action
+            // that opened it opens it again and waits for input on every
+            // repetition. Pressing Esc there stops the replay.
+            if let Some(recording) = &mut self.recording {
+                if !matches!(
+                    action,
+                    Action::ToggleRecording | Action::ReplayMacro
+                ) {
+                    recording.push(action);
+                }
+            }
+            self.perform(action);
+        }
+
+        self.scroll();
+        Ok(())
+    }
+
+    fn perform(&mut self, action: Action) {
+        match action {
+            Action::MoveUp => self.move_cursor(Key::Up),
+            Action::MoveDown => self.move_cursor(Key::Down),
+            Action::MoveLeft => self.move_cursor(Key::Left),
+            Action::MoveRight => self.move_cursor(Key::Right),
+            Action::PageUp => self.move_cursor(Key::PageUp),
+            Action::PageDown => self.move_cursor(Key::PageDown),
+            Action::Home => self.move_cursor(Key::Home),
+            Action::End => self.move_cursor(Key::End),
+            Action::InsertChar(c) => self.insert_char(c),
+            Action::InsertIndent => {
+                if self.document.is_read_only() {
+                    self.warn_if_read_only();
+                } else {
+                    self.cursor_position =
+                        self.document.insert_indent(&self.cursor_position);
                 }
-                self.should_quit = true;
             },
-            Key::Ctrl('s') => self.save(),
-            Key::Ctrl('f') => self.search(),
-            Key::Char(c) => {
-                self.document.insert(&self.cursor_position, c);
-                self.move_cursor(Key::Right);
+            Action::Delete => {
+                // Deleting can merge the graphemes on either side into one,
+                // shortening the row by more than the one deleted.
+                if self.document.delete(&self.cursor_position) {
+                    self.cursor_position =
+                        self.document.clamp_cursor(&self.cursor_position);
+                } else {
+                    self.warn_if_read_only();
+                }
+            },
+            Action::Backspace => {
+                if self.document.is_read_only() {
+                    self.warn_if_read_only();
+                } else {
+                    self.cursor_position = self
+                        .document
+                        .delete_indent_backward(&self.cursor_position);
+                }
             },
-            Key::Delete => self.document.delete(&self.cursor_position),
-            Key::Backspace => {
-                if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
-                    self.move_cursor(Key::Left);
-                    self.document.delete(&self.cursor_position);
+            Action::DeleteWordBackward => {
+                if self.document.is_read_only() {
+                    self.warn_if_read_only();
+                } else {
+                    self.cursor_position = self
+                        .document
+                        .delete_word_backward(&self.cursor_position);
                 }
             },
-            _ => self.move_cursor(pressed_key),
+            Action::Save => self.save(),
+            Action::OpenFile => self.open_file(),
+            Action::OpenRecent => self.open_recent(),
+            Action::ProjectSearch => self.project_search(),
+            Action::Search => self.search(),
+            Action::JumpBack => {
+                let position = self.jump_list.back(&self.cursor_position);
+                self.retrace_jump(position);
+            },
+            Action::JumpForward => {
+                let position = self.jump_list.forward();
+                self.retrace_jump(position);
+            },
+            Action::HighlightWord => self.toggle_highlight_all(),
+            Action::ToggleComment => self.toggle_comment(),
+            Action::MoveLineUp => self.move_line(SearchDirection::Backward),
+            Action::MoveLineDown => self.move_line(SearchDirection::Forward),
+            Action::NextAtIndent => self.move_to_indent(true),
+            Action::PreviousAtIndent => self.move_to_indent(false),
+            Action::DuplicateLine => self.duplicate_line(),
+            Action::ToggleFold => self.toggle_fold(),
+            Action::Sanitize => self.sanitize(),
+            Action::DedupLines => self.dedup_lines(),
+            Action::Retab => self.retab(),
+            Action::ToggleBom => self.toggle_bom(),
+            Action::InsertTimestamp => self.insert_timestamp(),
+            Action::Check => self.check(),
+            Action::Reload => self.reload(),
+            Action::ToggleLineNumbers => {
+                self.line_numbers = self.line_numbers.next();
+            },
+            Action::ToggleWhitespace => {
+                self.show_whitespace = !self.show_whitespace;
+            },
+            Action::ToggleRuler => self.show_ruler = !self.show_ruler,
+            Action::ToggleCurrentLine => {
+                self.show_current_line = !self.show_current_line;
+            },
+            Action::CommandPalette => self.command_palette(),
+            Action::ToggleRecording => self.toggle_recording(),
+            Action::ReplayMacro => self.replay_macro(),
+            Action::NextBuffer => self.switch_buffer(self.buffers.next_index()),
+            Action::PreviousBuffer => {
+                self.switch_buffer(self.buffers.previous_index());
+            },
+            Action::CloseBuffer => self.close_buffer(),
+            Action::Quit => self.quit(false),
+            Action::ForceQuit => self.quit(true),
         }
+    }
 
-        self.scroll();
+    /// Runs `cargo check` from the document's directory and marks the
+    /// errors and warnings it reports in the document. The editor waits for
+    /// it, as there is no way to draw its results before the next key press
+    /// otherwise.
+    fn check(&mut self) {
+        let file_name = match &self.document.file_name {
+            Some(file_name) if !self.document.is_dirty() => file_name.clone(),
+            _ => {
+                self.status_message =
+                    StatusMessage::from("Save the file before checking it.");
+                return;
+            },
+        };
+        self.status_message = StatusMessage::from("Running cargo check...");
+        if let Err(error) = self.refresh_screen() {
+            die(&error);
+        }
 
-        if self.quit_times < QUIT_TIMES {
-            self.quit_times = QUIT_TIMES;
-            self.status_message = StatusMessage::from("");
+        let path = Path::new(&file_name);
+        let mut command = Command::new("cargo");
+        command
+            .args(&["check", "--message-format=json"])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null());
+        if let Some(dir) =
+            path.parent().filter(|dir| !dir.as_os_str().is_empty())
+        {
+            command.current_dir(dir);
         }
 
-        Ok(())
+        match command.output() {
+            Ok(output) => {
+                let output = String::from_utf8_lossy(&output.stdout);
+                let diagnostics =
+                    cargo_diagnostics(&output, path, &self.document);
+                // A span over several lines gives a diagnostic for each, all
+                // in a row.
+                let mut reports: Vec<(Severity, &str)> = diagnostics
+                    .iter()
+                    .map(|diagnostic| {
+                        (diagnostic.severity, diagnostic.message.as_str())
+                    })
+                    .collect();
+                reports.dedup();
+                let errors = reports
+                    .iter()
+                    .filter(|(severity, _)| *severity == Severity::Error)
+                    .count();
+                let warnings = reports.len().saturating_sub(errors);
+                self.status_message = StatusMessage::new(format!(
+                    "cargo check: {} errors, {} warnings",
+                    errors, warnings
+                ));
+                self.document.set_diagnostics(diagnostics);
+            },
+            Err(error) => {
+                self.status_message = StatusMessage::new(format!(
+                    "ERR: Could not run cargo check: {}",
+                    error
+                ));
+            },
+        }
+    }
+
+    /// Quits, first asking for confirmation if any buffer has unsaved
+    /// changes, unless `force` is set. Anything but `y` keeps the editor
+    /// open.
+    fn quit(&mut self, force: bool) {
+        let dirty = self.document.is_dirty() || self.buffers.dirty_count() > 0;
+        if quit_decision(dirty, force) == QuitDecision::Confirm {
+            let answer = self
+                .prompt("Unsaved changes will be lost. Quit anyway? (y/N): ")
+                .unwrap_or_default();
+            if !confirms_quit(answer.as_deref()) {
+                self.status_message = StatusMessage::from("Quit aborted.");
+                return;
+            }
+        }
+        self.should_quit = true;
+    }
+
+    /// Inserts `c` at the cursor. With auto-pairing on, an opening bracket or
+    /// quote gets its closer inserted after the cursor, and typing a closer
+    /// that is already next to the cursor steps over it instead.
+    ///
+    /// A newline typed between an opening bracket and its closer puts the
+    /// closer on a line of its own and the cursor on an indented line in
+    /// between. In a directory listing, it opens the entry under the cursor
+    /// instead.
+    fn insert_char(&mut self, c: char) {
+        if c == '\n' && self.document.kind() == BufferKind::Directory {
+            self.open_entry();
+            return;
+        }
+        if self.document.is_read_only() {
+            self.warn_if_read_only();
+            return;
+        }
+        let Position { x, y } = self.cursor_position;
+        let before = x
+            .checked_sub(1)
+            .and_then(|x| self.document.char_at(&Position::new(x, y)));
+        let after = self.document.char_at(&self.cursor_position);
+
+        let in_brackets = matches!(before, Some('(') | Some('[') | Some('{'))
+            && after.is_some()
+            && after == before.and_then(pair_closer);
+        if c == '\n' && in_brackets {
+            self.cursor_position =
+                self.document.insert_newline_in_pair(&self.cursor_position);
+            return;
+        }
+
+        if self.auto_pair && is_closer(c) && after == Some(c) {
+            self.move_cursor(Key::Right);
+            return;
+        }
+
+        // Pairing right before a word, or a quote right after one (as in
+        // `don't` or `'a`), would more likely get in the way than help.
+        let closer = pair_closer(c).filter(|&closer| {
+            let is_quote = closer == c;
+            let touches_word = after.map_or(false, char::is_alphanumeric)
+                || (is_quote && before.map_or(false, char::is_alphanumeric));
+            self.auto_pair && !touches_word && !self.in_string_or_comment()
+        });
+        let row_len = self.document.row(y).map_or(0, Row::len);
+        if !self.document.insert(&self.cursor_position, c) {
+            return;
+        }
+        if let Some(closer) = closer {
+            self.document
+                .insert(&Position::new(x.saturating_add(1), y), closer);
+        }
+        // A combining char joins the grapheme before the cursor instead of
+        // adding one, so there's nothing to step over.
+        if c == '\n' || self.document.row(y).map_or(0, Row::len) > row_len {
+            self.move_cursor(Key::Right);
+        }
+    }
+
+    /// Whether the cursor sits inside a string or comment, going by the
+    /// highlighting on either side of it. A line comment also counts at the
+    /// end of its line.
+    fn in_string_or_comment(&self) -> bool {
+        let Position { x, y } = self.cursor_position;
+        let before = x
+            .checked_sub(1)
+            .and_then(|x| self.document.syntax_at(&Position::new(x, y)));
+        let after = self.document.syntax_at(&self.cursor_position);
+        let is_text = |hl_type| {
+            matches!(
+                hl_type,
+                highlighting::Type::String
+                    | highlighting::Type::Escape
+                    | highlighting::Type::Character
+                    | highlighting::Type::Comment
+                    | highlighting::Type::MultilineComment
+            )
+        };
+
+        match (before, after) {
+            (Some(before), Some(after)) => is_text(before) && is_text(after),
+            (Some(highlighting::Type::Comment), None) => true,
+            _ => false,
+        }
+    }
+
+    fn warn_if_read_only(&mut self) {
+        if self.document.is_read_only() {
+            self.status_message = StatusMessage::from("File is read-only.");
+            self.flash();
+        }
+    }
+
+    /// Rings the bell, visual or audible, to signal that something didn't
+    /// work, which also stops a macro replay. A bell that fails to ring
+    /// isn't worth interrupting the user for.
+    fn flash(&mut self) {
+        self.interrupted = true;
+        let _ = self.terminal.flash();
     }
 
     fn move_cursor(&mut self, key: Key) {
@@ -222,71 +1259,206 @@ impl<'a> Editor<'a> {
         if self.cursor_position.x > width {
             self.cursor_position.x = width;
         }
+
+        // Step over folded lines, to the line after the fold when moving
+        // forward and to its first line when moving back.
+        if let Some((first, last)) =
+            self.document.fold_at(self.cursor_position.y)
+        {
+            if self.cursor_position.y > first {
+                self.cursor_position = match key {
+                    Key::Down | Key::Right | Key::PageDown => {
+                        Position::new(0, last.saturating_add(1))
+                    },
+                    _ => Position::new(0, first),
+                };
+            }
+        }
     }
 
-    fn scroll(&mut self) {
+    /// The cursor position with `x` as a display column rather than a
+    /// grapheme index.
+    fn display_position(&self) -> Position {
         let Position { x, y } = self.cursor_position;
-        let width = self.terminal.size().width as usize;
-        let height = self.terminal.size().height as usize;
-        let mut offset = &mut self.offset;
+        let column = self.document.row(y).map_or(0, |row| row.column_of(x));
+        Position::new(column, self.document.display_line(y))
+    }
 
-        if y < offset.y {
-            offset.y = y;
-        } else if y >= offset.y.saturating_add(height) {
-            offset.y = y.saturating_sub(height).saturating_add(1);
+    /// Width of the line-number gutter, including the space separating it
+    /// from the text.
+    fn gutter_width(&self) -> usize {
+        if self.line_numbers == LineNumbers::Off {
+            return 0;
         }
+        self.document.len().to_string().len().saturating_add(1)
+    }
 
-        if x < offset.x {
-            offset.x = x;
-        } else if x >= offset.x.saturating_add(width) {
-            offset.x = x.saturating_sub(width).saturating_add(1);
+    /// Shrinks the view to the part of the screen left over by the gutter.
+    fn sync_view_width(&mut self) {
+        let gutter_width =
+            u16::try_from(self.gutter_width()).unwrap_or(u16::MAX);
+        self.view.size.width =
+            self.terminal.size().width.saturating_sub(gutter_width);
+    }
+
+    fn scroll(&mut self) {
+        self.sync_view_width();
+        let position = self.display_position();
+        let last_line = self.document.display_line(self.document.len());
+        self.view.scroll_to(&position, last_line);
+    }
+
+    /// Moves the cursor to `position`, centering the view on it if it was off
+    /// screen.
+    fn jump_to(&mut self, position: Position) {
+        if self.document.is_hidden(position.y) {
+            self.document.unfold(position.y);
+        }
+        self.sync_view_width();
+        self.cursor_position = position;
+        let position = self.display_position();
+        let last_line = self.document.display_line(self.document.len());
+        if self.view.contains(&position) {
+            self.view.scroll_to(&position, last_line);
+        } else {
+            self.view.center_on(&position, last_line);
         }
     }
 
-    pub fn draw_row(&self, row: &Row) {
-        let width = self.terminal.size().width as usize;
-        let start = self.offset.x;
-        let end = self.offset.x.saturating_add(width);
-        let row = row.render(start, end);
-        println!("{}\r", row);
+    fn draw_row(&mut self, row: &str) {
+        self.terminal.write_buf(row);
+        self.terminal.write_buf("\r\n");
     }
 
-    #[allow(clippy::integer_division, clippy::integer_arithmetic)]
-    fn draw_rows(&self) {
-        let height = self.terminal.size().height;
+    #[allow(clippy::integer_division, clippy::arithmetic_side_effects)]
+    fn draw_rows(&mut self) {
+        let height = self.view.height();
+        let start = self.view.offset.x;
+        let end = start.saturating_add(self.view.width());
+        let ruler = if self.show_ruler {
+            Some(self.ruler)
+        } else {
+            None
+        };
+        let rows: Vec<(usize, String)> = self
+            .view
+            .visible_rows(&self.document)
+            .map(|(y, row)| {
+                let background =
+                    if self.show_current_line && y == self.cursor_position.y {
+                        Some(self.current_line_color)
+                    } else {
+                        None
+                    };
+                let mut rendered = bg_string(background);
+                rendered.push_str(&row.render(
+                    start,
+                    end,
+                    self.hyperlinks,
+                    self.show_whitespace,
+                    ruler,
+                    background,
+                ));
+                let mut used = row.display_width().saturating_sub(start);
+                if let Some((first, last)) = self.document.fold_at(y) {
+                    let mut summary = format!(" ··· {} lines", last - first);
+                    summary = summary
+                        .chars()
+                        .take(end.saturating_sub(start).saturating_sub(used))
+                        .collect();
+                    used = used.saturating_add(summary.chars().count());
+                    let _ = write!(
+                        rendered,
+                        "{}{}{}",
+                        color::Fg(GUTTER_FG_COLOR),
+                        summary,
+                        color::Fg(color::Reset)
+                    );
+                }
+                // Rows ending before the ruler are padded out to it.
+                if let Some(ruler) = ruler {
+                    if ruler >= start.saturating_add(used) && ruler < end {
+                        let _ = write!(
+                            rendered,
+                            "{}{} {}",
+                            " ".repeat(ruler - start - used),
+                            color::Bg(RULER_COLOR),
+                            bg_string(background)
+                        );
+                        used = ruler - start + 1;
+                    }
+                }
+                // The cursor's line is shaded across the whole window.
+                if background.is_some() {
+                    let width = end.saturating_sub(start);
+                    let _ = write!(
+                        rendered,
+                        "{}{}",
+                        " ".repeat(width.saturating_sub(used)),
+                        color::Bg(color::Reset)
+                    );
+                }
+                (y, rendered)
+            })
+            .collect();
+        let mut rows = rows.into_iter();
 
         for terminal_row in 0..height {
-            Terminal::clear_current_line();
-            if let Some(row) = self
-                .document
-                .row(self.offset.y.saturating_add(terminal_row as usize))
-            {
-                self.draw_row(row);
+            self.terminal.clear_current_line();
+            if let Some((y, row)) = rows.next() {
+                self.draw_line_number(y);
+                self.draw_row(&row);
             } else if self.document.is_empty() && terminal_row == height / 3 {
                 self.draw_welcome_message();
             } else {
-                println!("{}~\r", color::Fg(color::LightBlue));
+                self.terminal.write_buf(&format!(
+                    "{}~\r\n",
+                    color::Fg(color::LightBlue)
+                ));
             }
         }
     }
 
-    fn draw_welcome_message(&self) {
+    fn draw_line_number(&mut self, y: usize) {
+        let width = self.gutter_width().saturating_sub(1);
+        let cursor_y = self.cursor_position.y;
+        let number = match self.line_numbers {
+            LineNumbers::Off => return,
+            LineNumbers::Relative if y != cursor_y => {
+                cmp::max(y, cursor_y).saturating_sub(cmp::min(y, cursor_y))
+            },
+            _ => y.saturating_add(1),
+        };
+
+        if y == cursor_y {
+            self.terminal.set_fg_color(CURRENT_LINE_NUMBER_COLOR);
+        } else {
+            self.terminal.set_fg_color(GUTTER_FG_COLOR);
+        }
+        self.terminal
+            .write_buf(&format!("{:>width$} ", number, width = width));
+        self.terminal.reset_fg_color();
+    }
+
+    fn draw_welcome_message(&mut self) {
         let mut welcome_message =
             format!("Hecto editor -- version {}\r", VERSION);
         let width = self.terminal.size().width as usize;
         let len = welcome_message.len();
-        #[allow(clippy::integer_division, clippy::integer_arithmetic)]
+        #[allow(clippy::integer_division, clippy::arithmetic_side_effects)]
         let padding = width.saturating_sub(len) / 2;
         let spaces = " ".repeat(padding.saturating_sub(1));
         welcome_message = format!("~{}{}", spaces, welcome_message);
         welcome_message.truncate(width);
-        println!("{}\r", welcome_message);
+        self.draw_row(&welcome_message);
     }
 
-    fn draw_status_bar(&self) {
+    fn draw_status_bar(&mut self) {
         let mut status;
         let width = self.terminal.size().width as usize;
-        let modified_indicator = if self.document.is_dirty() {
+        let modified_indicator = if self.document.is_read_only() {
+            " (read-only)"
+        } else if self.document.is_dirty() {
             " (modified)"
         } else {
             ""
@@ -294,44 +1466,81 @@ impl<'a> Editor<'a> {
         let mut file_name = "[No Name]".to_string();
 
         if let Some(name) = &self.document.file_name {
-            file_name = name.clone();
-            file_name.truncate(20);
+            file_name.clone_from(name);
+            truncate_to_width(&mut file_name, 20);
         }
+        let doc_stats = self.document.stats();
+        let buffer = if self.buffers.count() > 1 {
+            format!(
+                "[{}/{}] ",
+                self.buffers.active_index().saturating_add(1),
+                self.buffers.count()
+            )
+        } else {
+            String::new()
+        };
         status = format!(
-            "{} - {} lines{}",
+            "{}{} - {} lines, {} words{}",
+            buffer,
             file_name,
-            self.document.len(),
+            doc_stats.lines,
+            doc_stats.words,
             modified_indicator
         );
 
+        let matches = match self.search_matches {
+            Some((_, 0)) => "No matches | ".to_string(),
+            Some((Some(ordinal), total)) => {
+                format!("{} of {} matches | ", ordinal, total)
+            },
+            Some((None, 1)) => "1 match | ".to_string(),
+            Some((None, total)) => format!("{} matches | ", total),
+            None => String::new(),
+        };
+        let recording = if self.recording.is_some() {
+            "Recording | "
+        } else {
+            ""
+        };
+        let encoding = self.document.encoding();
+        let encoding = if encoding.is_plain_utf8() {
+            String::new()
+        } else if encoding.has_bom() {
+            format!("{} BOM | ", encoding.name())
+        } else {
+            format!("{} | ", encoding.name())
+        };
         let line_indicator = format!(
-            "{} | {}/{}",
+            "{}{}{}{} | {}/{}",
+            recording,
+            matches,
+            encoding,
             self.document.file_type(),
             self.cursor_position.y.saturating_add(1),
             self.document.len()
         );
 
-        #[allow(clippy::integer_arithmetic)]
-        let len = status.len() + line_indicator.len();
+        #[allow(clippy::arithmetic_side_effects)]
+        let len = status.width() + line_indicator.width();
         status.push_str(&" ".repeat(width.saturating_sub(len)));
 
         status = format!("{}{}", status, line_indicator);
-        status.truncate(width);
+        truncate_to_width(&mut status, width);
 
-        Terminal::set_bg_color(STATUS_BG_COLOR);
-        Terminal::set_fg_color(STATUS_FG_COLOR);
-        println!("{}\r", status);
-        Terminal::reset_fg_color();
-        Terminal::reset_bg_color();
+        self.terminal.set_bg_color(STATUS_BG_COLOR);
+        self.terminal.set_fg_color(STATUS_FG_COLOR);
+        self.draw_row(&status);
+        self.terminal.reset_fg_color();
+        self.terminal.reset_bg_color();
     }
 
-    fn draw_message_bar(&self) {
-        Terminal::clear_current_line();
+    fn draw_message_bar(&mut self) {
+        self.terminal.clear_current_line();
         let message = &self.status_message;
-        if Instant::now() - message.time < Duration::new(5, 0) {
+        if message.time.elapsed() < Duration::new(5, 0) {
             let mut text = message.text.clone();
-            text.truncate(self.terminal.size().width as usize);
-            print!("{}", text);
+            truncate_to_width(&mut text, self.terminal.size().width as usize);
+            self.terminal.write_buf(&text);
         }
     }
 
@@ -342,18 +1551,15 @@ impl<'a> Editor<'a> {
                 StatusMessage::new(format!("{}{}", prompt, result));
             self.refresh_screen()?;
 
-            match Terminal::read_key()? {
+            match self.terminal.read_key()? {
                 Key::Backspace => {
-                    result.truncate(result.len().saturating_sub(1))
+                    result.truncate(result.len().saturating_sub(1));
                 },
                 Key::Char('\n') => break,
-                Key::Char(c) => {
-                    if !c.is_control() {
-                        result.push(c);
-                    }
-                },
+                Key::Char(c) if !c.is_control() => result.push(c),
                 Key::Esc => {
                     result.truncate(0);
+                    self.interrupted = true;
                     break;
                 },
                 _ => (),
@@ -383,20 +1589,17 @@ impl<'a> Editor<'a> {
                 StatusMessage::new(format!("{}{}", prompt, result));
             self.refresh_screen()?;
 
-            let key = Terminal::read_key()?;
+            let key = self.terminal.read_key()?;
 
             match key {
                 Key::Backspace => {
-                    result.truncate(result.len().saturating_sub(1))
+                    result.truncate(result.len().saturating_sub(1));
                 },
                 Key::Char('\n') => break,
-                Key::Char(c) => {
-                    if !c.is_control() {
-                        result.push(c);
-                    }
-                },
+                Key::Char(c) if !c.is_control() => result.push(c),
                 Key::Esc => {
                     result.truncate(0);
+                    self.interrupted = true;
                     break;
                 },
                 _ => (),
@@ -415,22 +1618,78 @@ impl<'a> Editor<'a> {
     }
 }
 
-fn die(e: std::io::Error) {
-    Terminal::clear_screen();
-    panic!(e);
+/// The char that closes a pair opened by `c`, if `c` opens one.
+const fn pair_closer(c: char) -> Option<char> {
+    match c {
+        '(' => Some(')'),
+        '[' => Some(']'),
+        '{' => Some('}'),
+        '"' => Some('"'),
+        '\'' => Some('\''),
+        _ => None,
+    }
+}
+
+const fn is_closer(c: char) -> bool {
+    matches!(c, ')' | ']' | '}' | '"' | '\'')
+}
+
+/// What asking to quit does.
+#[derive(Debug, PartialEq, Eq)]
+enum QuitDecision {
+    Quit,
+    /// Ask first, since unsaved changes would be lost.
+    Confirm,
+}
+
+/// Quitting asks first only if there are unsaved changes and it isn't
+/// forced.
+const fn quit_decision(dirty: bool, force: bool) -> QuitDecision {
+    if dirty && !force {
+        QuitDecision::Confirm
+    } else {
+        QuitDecision::Quit
+    }
+}
+
+/// Whether `answer` to the quit prompt confirms quitting: only `y` does,
+/// and cancelling the prompt gives `None`.
+fn confirms_quit(answer: Option<&str>) -> bool {
+    answer == Some("y")
+}
+
+fn die(e: &std::io::Error) {
+    panic!("{}", e);
 }
 
-#[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Default, Clone)]
+/// A place in a document: the grapheme `x` of the row `y`. Positions are
+/// ordered as they appear in the document, row by row, so `Ord::min` and
+/// `Ord::max` give the earlier and later of two.
+#[derive(Debug, Eq, PartialEq, Default, Clone)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
 }
 
+impl Ord for Position {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        (self.y, self.x).cmp(&(other.y, other.x))
+    }
+}
+
+impl PartialOrd for Position {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl Position {
+    #[must_use]
     pub fn new(x: usize, y: usize) -> Self {
         Self { x, y }
     }
 
+    #[must_use]
     pub fn with_offset(&self, offset: &Self) -> Self {
         Position::new(
             self.x.saturating_sub(offset.x),
@@ -461,10 +1720,8 @@ impl Position {
                     }
                 }
             },
-            Key::Down => {
-                if self.y < height {
-                    self.y = self.y.saturating_add(1);
-                }
+            Key::Down if self.y < height => {
+                self.y = self.y.saturating_add(1);
             },
             Key::Right => {
                 if self.x < width {
@@ -490,7 +1747,17 @@ impl Position {
                     height
                 }
             },
-            Key::Home => self.x = 0,
+            // Home goes to the first non-blank grapheme, or to the start of
+            // the line if the cursor is already there.
+            Key::Home => {
+                let first_non_blank =
+                    doc.row(self.y).map_or(0, Row::first_non_blank);
+                self.x = if self.x == first_non_blank {
+                    0
+                } else {
+                    first_non_blank
+                };
+            },
             Key::End => self.x = width,
             _ => (),
         }
@@ -517,8 +1784,68 @@ impl From<&str> for StatusMessage {
     }
 }
 
+/// How the line-number gutter is drawn.
+#[derive(PartialEq, Copy, Clone)]
+pub enum LineNumbers {
+    Off,
+    Absolute,
+    /// Distance from the cursor row; the cursor row shows its own number.
+    Relative,
+}
+
+impl LineNumbers {
+    const fn next(self) -> Self {
+        match self {
+            LineNumbers::Off => LineNumbers::Absolute,
+            LineNumbers::Absolute => LineNumbers::Relative,
+            LineNumbers::Relative => LineNumbers::Off,
+        }
+    }
+}
+
 #[derive(PartialEq, Copy, Clone)]
 pub enum SearchDirection {
     Forward,
     Backward,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quitting_asks_only_with_unsaved_changes() {
+        assert_eq!(quit_decision(true, false), QuitDecision::Confirm);
+        assert_eq!(quit_decision(false, false), QuitDecision::Quit);
+        assert_eq!(quit_decision(true, true), QuitDecision::Quit);
+        assert_eq!(quit_decision(false, true), QuitDecision::Quit);
+    }
+
+    #[test]
+    fn only_y_confirms_quitting() {
+        assert!(confirms_quit(Some("y")));
+        assert!(!confirms_quit(Some("n")));
+        assert!(!confirms_quit(Some("")));
+        assert!(!confirms_quit(Some("yes")));
+        assert!(!confirms_quit(None));
+    }
+
+    #[test]
+    fn clamping_snaps_onto_an_existing_row() {
+        let mut doc = Document::default();
+        let origin = Position::default();
+        assert_eq!(doc.clamp_cursor(&Position::new(3, 2)), origin);
+
+        doc.insert_str(&origin, "a\nbc");
+        // The line just past the end is clamped like any other, onto the
+        // last row.
+        for (pos, clamped) in &[
+            (Position::new(5, 2), Position::new(2, 1)),
+            (Position::new(0, 9), Position::new(0, 1)),
+            (Position::new(4, 0), Position::new(1, 0)),
+            (Position::new(1, 1), Position::new(1, 1)),
+        ] {
+            assert_eq!(&doc.clamp_cursor(pos), clamped);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use encoding_rs::{DecoderResult, Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+use std::{io, str};
+
+/// The character encoding a file is stored in, and whether it starts with a
+/// byte order mark. Documents hold their text as UTF-8 and convert from and
+/// to this when reading and writing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileEncoding {
+    encoding: &'static Encoding,
+    bom: bool,
+}
+
+impl Default for FileEncoding {
+    fn default() -> Self {
+        Self {
+            encoding: UTF_8,
+            bom: false,
+        }
+    }
+}
+
+impl FileEncoding {
+    /// The WHATWG name of the encoding, such as `UTF-8` or `windows-1252`.
+    pub fn name(&self) -> &'static str {
+        self.encoding.name()
+    }
+
+    pub const fn has_bom(&self) -> bool {
+        self.bom
+    }
+
+    /// UTF-8 with or without a byte order mark, as `bom` says. Other
+    /// encodings give `None`: UTF-16 can't be told apart without its mark,
+    /// and the rest have none.
+    pub(crate) fn with_bom(self, bom: bool) -> Option<Self> {
+        if self.encoding == UTF_8 {
+            Some(Self { bom, ..self })
+        } else {
+            None
+        }
+    }
+
+    pub(crate) const fn encoding(&self) -> &'static Encoding {
+        self.encoding
+    }
+
+    /// Whether this is UTF-8 without a byte order mark, which needs no
+    /// conversion at all.
+    pub(crate) fn is_plain_utf8(&self) -> bool {
+        self.encoding == UTF_8 && !self.bom
+    }
+
+    /// Decodes `bytes` in the encoding their byte order mark names. Without
+    /// one they are taken as UTF-8 if they are valid UTF-8, and as
+    /// `fallback` otherwise. Fails with the offset of the first bytes that
+    /// aren't valid in the encoding, rather than replacing them, so that
+    /// saving can't silently corrupt the file.
+    pub(crate) fn decode(
+        bytes: &[u8],
+        fallback: &'static Encoding,
+    ) -> Result<(String, Self), io::Error> {
+        let (encoding, bom_len) =
+            Encoding::for_bom(bytes).unwrap_or_else(|| {
+                if str::from_utf8(bytes).is_ok() {
+                    (UTF_8, 0)
+                } else {
+                    (fallback, 0)
+                }
+            });
+        #[allow(clippy::indexing_slicing)]
+        let body = &bytes[bom_len..];
+
+        let mut decoder = encoding.new_decoder_without_bom_handling();
+        let capacity = decoder
+            .max_utf8_buffer_length_without_replacement(body.len())
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "file too large")
+            })?;
+        let mut text = String::with_capacity(capacity);
+        let (result, read) =
+            decoder.decode_to_string_without_replacement(body, &mut text, true);
+
+        match result {
+            DecoderResult::InputEmpty => Ok((
+                text,
+                Self {
+                    encoding,
+                    bom: bom_len > 0,
+                },
+            )),
+            DecoderResult::Malformed(bad, after) => {
+                let end = read.saturating_sub(usize::from(after));
+                let start = end.saturating_sub(usize::from(bad));
+                let invalid: Vec<String> = body
+                    .get(start..end)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|byte| format!("{:#04x}", byte))
+                    .collect();
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "invalid {} at byte {}: {}",
+                        encoding.name(),
+                        start.saturating_add(bom_len),
+                        invalid.join(" ")
+                    ),
+                ))
+            },
+            DecoderResult::OutputFull => Err(io::Error::new(
+                io::ErrorKind::Other,
+                "could not decode the whole file",
+            )),
+        }
+    }
+
+    /// Encodes `text`, starting with a byte order mark if the file had one.
+    /// Fails if `text` has chars the encoding can't represent.
+    pub(crate) fn encode(&self, text: &str) -> Result<Vec<u8>, io::Error> {
+        let mut bytes = Vec::with_capacity(text.len());
+
+        if self.encoding == UTF_16LE || self.encoding == UTF_16BE {
+            let big_endian = self.encoding == UTF_16BE;
+            let units = (if self.bom { "\u{feff}" } else { "" })
+                .encode_utf16()
+                .chain(text.encode_utf16());
+            for unit in units {
+                if big_endian {
+                    bytes.extend_from_slice(&unit.to_be_bytes());
+                } else {
+                    bytes.extend_from_slice(&unit.to_le_bytes());
+                }
+            }
+            return Ok(bytes);
+        }
+
+        if self.bom {
+            bytes.extend_from_slice("\u{feff}".as_bytes());
+        }
+        let (encoded, _, unmappable) = self.encoding.encode(text);
+        if unmappable {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("text can't be represented in {}", self.name()),
+            ));
+        }
+        bytes.extend_from_slice(&encoded);
+        Ok(bytes)
+    }
+}
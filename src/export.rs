@@ -0,0 +1,106 @@
+use crate::{highlighting, Document};
+
+use termion::color;
+use unicode_segmentation::UnicodeSegmentation;
+
+use std::fmt::Write;
+
+/// Background for [`html_css`], which the default colors are chosen to sit
+/// on.
+const BACKGROUND: &str = "#1e1e1e";
+
+/// Renders `document` as highlighted HTML: a `<pre class="hecto">` block in
+/// which each run of highlighted text is a `<span>` whose class names its
+/// highlighting type, e.g. `hl-keyword`. Whitespace is kept as it is. Only
+/// the syntax is highlighted, not matches or diagnostics. Style it with
+/// [`html_css`].
+pub fn to_html(document: &mut Document) -> String {
+    document.with_syntax_highlighting(rows_to_html)
+}
+
+#[allow(clippy::indexing_slicing)]
+fn rows_to_html(document: &Document) -> String {
+    let mut html = String::from("<pre class=\"hecto\">");
+    for row in document.rows() {
+        let graphemes: Vec<&str> = row.string[..].graphemes(true).collect();
+
+        for (start, end, hl_type) in row.highlight_spans() {
+            let text = escape_html(&graphemes[start..end].concat());
+            if let Some(class) = hl_type.css_class() {
+                let _ =
+                    write!(html, "<span class=\"{}\">{}</span>", class, text);
+            } else {
+                html.push_str(&text);
+            }
+        }
+        html.push('\n');
+    }
+    html.push_str("</pre>\n");
+    html
+}
+
+/// A stylesheet giving [`to_html`] output the editor's default colors.
+pub fn html_css() -> String {
+    let mut css = String::new();
+    for hl_type in &highlighting::Type::ALL {
+        let color::Rgb(r, g, b) = hl_type.to_color();
+        let selector = match hl_type.css_class() {
+            Some(class) => format!(".hecto .{}", class),
+            None => String::from(".hecto"),
+        };
+        let _ = writeln!(
+            css,
+            "{} {{ color: #{:02x}{:02x}{:02x}; }}",
+            selector, r, g, b
+        );
+    }
+    let _ = writeln!(css, ".hecto {{ background: {}; }}", BACKGROUND);
+    css
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Diagnostic, Position, Severity};
+
+    #[test]
+    fn spans_are_classed_and_text_escaped() {
+        let mut document = Document::default();
+        document.insert_str(&Position::default(), "let s = \"<a&b>\";");
+        document.set_file_name("a.rs");
+        document.highlight_all("s");
+        document.set_diagnostics(vec![Diagnostic {
+            line: 0,
+            col_start: 0,
+            col_end: 3,
+            severity: Severity::Error,
+            message: String::from("oops"),
+        }]);
+        let html =
+            "<pre class=\"hecto\"><span class=\"hl-keyword\">let</span> s \
+             <span class=\"hl-operator\">=</span> <span \
+             class=\"hl-string\">&quot;&lt;a&amp;b&gt;&quot;</span>;\n</pre>\n";
+        assert_eq!(to_html(&mut document), html);
+
+        document.highlight(None, None);
+        assert_eq!(document.highlighted_all(), Some("s"));
+        assert_eq!(
+            document.row(0).unwrap().highlight_spans().first(),
+            Some(&(0, 3, highlighting::Type::Error))
+        );
+    }
+}
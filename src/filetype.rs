@@ -1,63 +1,661 @@
-pub struct FileType<'a> {
+use crate::{picker::glob_matches, IndentStyle};
+
+use std::{cell::RefCell, ffi::OsStr, path::Path};
+
+thread_local! {
+    /// The rules `FileType::from` picks filetypes by, in the order they
+    /// were added.
+    static REGISTRY: RefCell<Vec<(Pattern, FileType)>> =
+        RefCell::new(FileType::builtins());
+}
+
+/// What a file's name has to look like for a filetype to apply to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Pattern {
+    /// An exact file name, such as `Makefile`.
+    Name(String),
+    /// A glob matched against the whole file name, such as `Dockerfile.*`,
+    /// where `*` stands for any run of chars and `?` for any one char.
+    Glob(String),
+    /// A lowercase extension without the dot, matched regardless of case.
+    Extension(String),
+}
+
+impl Pattern {
+    /// Reads `*.ext` as an extension, other patterns with `*` or `?` as
+    /// globs, and the rest as exact file names.
+    fn parse(pattern: &str) -> Self {
+        let is_glob = |s: &str| s.contains(&['*', '?'][..]);
+        match pattern.strip_prefix("*.") {
+            Some(extension) if !is_glob(extension) => {
+                Self::Extension(extension.to_ascii_lowercase())
+            },
+            _ if is_glob(pattern) => Self::Glob(pattern.to_string()),
+            _ => Self::Name(pattern.to_string()),
+        }
+    }
+
+    fn matches(&self, name: &str, extension: Option<&str>) -> bool {
+        match self {
+            Self::Name(pattern) => pattern == name,
+            Self::Glob(pattern) => glob_matches(pattern, name),
+            Self::Extension(pattern) => extension.map_or(false, |extension| {
+                pattern.eq_ignore_ascii_case(extension)
+            }),
+        }
+    }
+
+    /// How specific the pattern is. A file matched by several patterns
+    /// gets the filetype of the most specific one.
+    const fn specificity(&self) -> u8 {
+        match self {
+            Self::Name(_) => 2,
+            Self::Glob(_) => 1,
+            Self::Extension(_) => 0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct FileType {
     name: String,
-    hl_opts: HighlightingOptions<'a>,
+    /// How the language is conventionally indented, if it has a clear
+    /// convention. Other files use the editor's configured style.
+    indent_style: Option<IndentStyle>,
+    /// How many columns a tab is conventionally taken to span, if that
+    /// differs from the indent width.
+    tab_width: Option<usize>,
+    hl_opts: HighlightingOptions,
 }
 
-#[derive(Default)]
-pub struct HighlightingOptions<'a> {
-    numbers: bool,
-    strings: bool,
-    characters: bool,
-    comments: bool,
-    multiline_comments: bool,
-    primary_keywords: &'a [&'a str],
-    secondary_keywords: &'a [&'a str],
+#[derive(Default, Clone)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct HighlightingOptions {
+    pub numbers: bool,
+    /// Chars that open a string, which the same char then closes. Char
+    /// literals take precedence when `characters` is set, so languages that
+    /// use `'` for chars should leave it out.
+    pub string_delimiters: Vec<char>,
+    /// Like `string_delimiters`, but for strings that are taken literally,
+    /// without escapes or variables.
+    pub raw_string_delimiters: Vec<char>,
+    /// Delimiters of strings that are taken literally and may contain the
+    /// other delimiters, such as Lua's `[[` and `]]`. They are highlighted
+    /// up to the end of their line.
+    pub long_string: Option<(String, String)>,
+    pub characters: bool,
+    pub comment_leader: Option<String>,
+    /// Other leaders that start a line comment too, but aren't used to
+    /// comment lines out.
+    pub extra_comment_leaders: Vec<String>,
+    pub multiline_comment: Option<(String, String)>,
+    /// Whether the `multiline_comment` delimiters only count at the start
+    /// of a line, where they comment out the whole line, as Ruby's `=begin`
+    /// and `=end` do.
+    pub multiline_comment_at_line_start: bool,
+    pub variables: bool,
+    /// Whether `$(name)` is a variable rather than a command substitution,
+    /// and `$<`, `$^`, `$+` and `$%` are variables too, as in Makefiles.
+    pub make_variables: bool,
+    /// Whether the names before the colon of a line like `name: deps` are
+    /// highlighted as targets, as in Makefiles.
+    pub targets: bool,
+    /// Whether names prefixed with a colon, like Ruby's `:name`, are
+    /// symbols.
+    pub symbols: bool,
+    /// Whether lines are `[section]` headers or `key = value` pairs, as in
+    /// INI files, in which case comments only start at the start of a line
+    /// and nothing else is highlighted.
+    pub key_values: bool,
+    pub tags: bool,
+    pub primary_keywords: Vec<String>,
+    pub secondary_keywords: Vec<String>,
+    /// Match keywords regardless of ASCII case, for languages such as SQL
+    /// where `select` and `SELECT` are the same keyword.
+    pub keywords_case_insensitive: bool,
+    /// Whether keywords only count as the first word of a line, as
+    /// Dockerfile instructions do.
+    pub keywords_at_line_start: bool,
+    /// Operators and punctuation such as `+`, `=>` or `::`. Unlike
+    /// keywords they match next to names, and the longest one wins.
+    pub operators: Vec<String>,
 }
 
-impl<'a> Default for FileType<'a> {
+impl Default for FileType {
     fn default() -> Self {
         Self {
             name: "No filetype".into(),
+            indent_style: None,
+            tab_width: None,
             hl_opts: HighlightingOptions::default(),
         }
     }
 }
 
-impl<'a> From<&str> for FileType<'a> {
+/// Picks the filetype of the file at the path `ft` by the rules its file
+/// name matches: an exact name rule over a glob over an extension, and the
+/// rule added last among equally specific ones.
+impl From<&str> for FileType {
     fn from(ft: &str) -> Self {
-        if ft.ends_with(".rs") {
-            Self {
-                name: "Rust".into(),
-                hl_opts: HighlightingOptions {
-                    numbers: true,
-                    strings: true,
-                    characters: true,
-                    comments: true,
-                    multiline_comments: true,
-                    primary_keywords: &[
-                        "as", "break", "const", "continue", "crate", "else",
-                        "enum", "extern", "false", "fn", "for", "if", "impl",
-                        "in", "let", "loop", "match", "mod", "move", "mut",
-                        "pub", "ref", "return", "self", "Self", "static",
-                        "struct", "super", "trait", "true", "type", "unsafe",
-                        "use", "where", "while", "dyn", "abstract", "become",
-                        "box", "do", "final", "macro", "override", "priv",
-                        "typeof", "unsized", "virtual", "yield", "async",
-                        "await", "try",
-                    ],
-                    secondary_keywords: &[
-                        "bool", "char", "i8", "i16", "i32", "i64", "isize",
-                        "u8", "u16", "u32", "u64", "usize", "f32", "f64",
-                    ],
-                },
-            }
-        } else {
-            Self::default()
+        let path = Path::new(ft);
+        let name = match path.file_name().and_then(OsStr::to_str) {
+            Some(name) => name,
+            None => return Self::default(),
+        };
+        let extension = path.extension().and_then(OsStr::to_str);
+
+        REGISTRY
+            .with(|registry| {
+                registry
+                    .borrow()
+                    .iter()
+                    .filter(|(pattern, _)| pattern.matches(name, extension))
+                    .max_by_key(|(pattern, _)| pattern.specificity())
+                    .map(|(_, file_type)| file_type.clone())
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl FileType {
+    /// Makes files matching any of `patterns` use a filetype called `name`,
+    /// taking precedence over rules registered before with patterns of the
+    /// same kind. A pattern is an extension written as `*.ext`, a glob with
+    /// `*` or `?` matched against the whole file name, or an exact file
+    /// name. Without an `indent_style`, its files use the editor's
+    /// configured one.
+    pub fn register(
+        patterns: &[&str],
+        name: &str,
+        hl_opts: HighlightingOptions,
+        indent_style: Option<IndentStyle>,
+        tab_width: Option<usize>,
+    ) {
+        let file_type = Self {
+            name: name.into(),
+            indent_style,
+            tab_width,
+            hl_opts,
+        };
+        REGISTRY.with(|registry| {
+            file_type.add_to(&mut registry.borrow_mut(), patterns);
+        });
+    }
+
+    fn builtins() -> Vec<(Pattern, Self)> {
+        let mut rules = Vec::new();
+        Self::rust().add_to(&mut rules, &["*.rs"]);
+        Self::go().add_to(&mut rules, &["*.go"]);
+        Self::shell().add_to(&mut rules, &["*.sh", "*.bash"]);
+        Self::markup("HTML").add_to(&mut rules, &["*.html", "*.htm"]);
+        Self::markup("XML").add_to(&mut rules, &["*.xml"]);
+        Self::sql().add_to(&mut rules, &["*.sql"]);
+        Self::lua().add_to(&mut rules, &["*.lua"]);
+        Self::ruby().add_to(&mut rules, &["*.rb", "Gemfile", "Rakefile"]);
+        Self::ini().add_to(&mut rules, &["*.ini", "*.cfg", "*.properties"]);
+        Self::javascript("JavaScript", &[])
+            .add_to(&mut rules, &["*.js", "*.jsx", "*.mjs", "*.cjs"]);
+        Self::typescript()
+            .add_to(&mut rules, &["*.ts", "*.tsx", "*.mts", "*.cts"]);
+        Self::dockerfile().add_to(
+            &mut rules,
+            &[
+                "Dockerfile",
+                "Containerfile",
+                "Dockerfile.*",
+                "*.dockerfile",
+            ],
+        );
+        Self::makefile().add_to(
+            &mut rules,
+            &["Makefile", "makefile", "GNUmakefile", "*.mk"],
+        );
+        rules
+    }
+
+    fn add_to(self, rules: &mut Vec<(Pattern, Self)>, patterns: &[&str]) {
+        for pattern in patterns {
+            rules.push((Pattern::parse(pattern), self.clone()));
+        }
+    }
+
+    fn rust() -> Self {
+        Self {
+            name: "Rust".into(),
+            indent_style: Some(IndentStyle::Spaces(4)),
+            tab_width: None,
+            hl_opts: HighlightingOptions {
+                numbers: true,
+                string_delimiters: vec!['"'],
+                characters: true,
+                comment_leader: Some("//".into()),
+                multiline_comment: Some(("/*".into(), "*/".into())),
+                primary_keywords: owned(&[
+                    "as", "break", "const", "continue", "crate", "else",
+                    "enum", "extern", "false", "fn", "for", "if", "impl", "in",
+                    "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+                    "return", "self", "Self", "static", "struct", "super",
+                    "trait", "true", "type", "unsafe", "use", "where", "while",
+                    "dyn", "abstract", "become", "box", "do", "final", "macro",
+                    "override", "priv", "typeof", "unsized", "virtual",
+                    "yield", "async", "await", "try",
+                ]),
+                secondary_keywords: owned(&[
+                    "bool", "char", "i8", "i16", "i32", "i64", "isize", "u8",
+                    "u16", "u32", "u64", "usize", "f32", "f64",
+                ]),
+                operators: owned(&[
+                    "+", "-", "*", "/", "%", "^", "!", "&", "|", "&&", "||",
+                    "<<", ">>", "+=", "-=", "*=", "/=", "%=", "^=", "&=", "|=",
+                    "<<=", ">>=", "=", "==", "!=", ">", "<", ">=", "<=", "->",
+                    "=>", "::", "?", "..", "..=",
+                ]),
+                ..HighlightingOptions::default()
+            },
+        }
+    }
+
+    fn go() -> Self {
+        Self {
+            name: "Go".into(),
+            indent_style: Some(IndentStyle::Tabs),
+            tab_width: Some(8),
+            hl_opts: HighlightingOptions {
+                numbers: true,
+                string_delimiters: vec!['"'],
+                raw_string_delimiters: vec!['`'],
+                characters: true,
+                comment_leader: Some("//".into()),
+                multiline_comment: Some(("/*".into(), "*/".into())),
+                primary_keywords: owned(&[
+                    "break",
+                    "case",
+                    "chan",
+                    "const",
+                    "continue",
+                    "default",
+                    "defer",
+                    "else",
+                    "fallthrough",
+                    "for",
+                    "func",
+                    "go",
+                    "goto",
+                    "if",
+                    "import",
+                    "interface",
+                    "map",
+                    "package",
+                    "range",
+                    "return",
+                    "select",
+                    "struct",
+                    "switch",
+                    "type",
+                    "var",
+                    "true",
+                    "false",
+                    "nil",
+                    "iota",
+                ]),
+                secondary_keywords: owned(&[
+                    "bool",
+                    "byte",
+                    "rune",
+                    "string",
+                    "error",
+                    "any",
+                    "int",
+                    "int8",
+                    "int16",
+                    "int32",
+                    "int64",
+                    "uint",
+                    "uint8",
+                    "uint16",
+                    "uint32",
+                    "uint64",
+                    "uintptr",
+                    "float32",
+                    "float64",
+                    "complex64",
+                    "complex128",
+                ]),
+                operators: owned(&[
+                    "+", "-", "*", "/", "%", "&", "|", "^", "<<", ">>", "&^",
+                    "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "<<=",
+                    ">>=", "&^=", "&&", "||", "<-", "++", "--", "==", "<", ">",
+                    "=", "!", "~", "!=", "<=", ">=", ":=", "...",
+                ]),
+                ..HighlightingOptions::default()
+            },
+        }
+    }
+
+    fn markup(name: &str) -> Self {
+        Self {
+            name: name.into(),
+            indent_style: Some(IndentStyle::Spaces(2)),
+            tab_width: None,
+            hl_opts: HighlightingOptions {
+                multiline_comment: Some(("<!--".into(), "-->".into())),
+                tags: true,
+                ..HighlightingOptions::default()
+            },
+        }
+    }
+
+    fn sql() -> Self {
+        Self {
+            name: "SQL".into(),
+            indent_style: None,
+            tab_width: None,
+            hl_opts: HighlightingOptions {
+                numbers: true,
+                string_delimiters: vec!['\''],
+                comment_leader: Some("--".into()),
+                multiline_comment: Some(("/*".into(), "*/".into())),
+                primary_keywords: owned(&[
+                    "select", "from", "where", "join", "inner", "outer",
+                    "left", "right", "full", "cross", "on", "using", "insert",
+                    "into", "values", "update", "set", "delete", "create",
+                    "alter", "drop", "table", "view", "index", "database",
+                    "schema", "primary", "foreign", "key", "unique", "default",
+                    "and", "or", "not", "in", "is", "null", "like", "between",
+                    "exists", "as", "distinct", "group", "by", "order",
+                    "having", "limit", "offset", "union", "all", "case",
+                    "when", "then", "else", "end", "asc", "desc", "begin",
+                    "commit", "rollback", "with", "true", "false",
+                ]),
+                secondary_keywords: owned(&[
+                    "int", "integer", "smallint", "bigint", "serial",
+                    "decimal", "numeric", "real", "float", "double", "char",
+                    "varchar", "text", "blob", "boolean", "date", "time",
+                    "interval", "count", "sum", "avg", "min", "max",
+                    "coalesce",
+                ]),
+                keywords_case_insensitive: true,
+                ..HighlightingOptions::default()
+            },
+        }
+    }
+
+    /// Lua. Long brackets of a level above zero, like `[==[`, aren't
+    /// recognized.
+    fn lua() -> Self {
+        Self {
+            name: "Lua".into(),
+            indent_style: None,
+            tab_width: None,
+            hl_opts: HighlightingOptions {
+                numbers: true,
+                string_delimiters: vec!['"', '\''],
+                long_string: Some(("[[".into(), "]]".into())),
+                comment_leader: Some("--".into()),
+                multiline_comment: Some(("--[[".into(), "]]".into())),
+                primary_keywords: owned(&[
+                    "and", "break", "do", "else", "elseif", "end", "false",
+                    "for", "function", "goto", "if", "in", "local", "nil",
+                    "not", "or", "repeat", "return", "then", "true", "until",
+                    "while",
+                ]),
+                secondary_keywords: owned(&[
+                    "self", "print", "pairs", "ipairs", "type", "tostring",
+                    "tonumber", "require", "error", "assert", "pcall",
+                    "select", "next", "rawget", "rawset", "unpack",
+                ]),
+                ..HighlightingOptions::default()
+            },
+        }
+    }
+
+    fn ini() -> Self {
+        Self {
+            name: "INI".into(),
+            indent_style: None,
+            tab_width: None,
+            hl_opts: HighlightingOptions {
+                comment_leader: Some(";".into()),
+                extra_comment_leaders: vec!["#".into()],
+                key_values: true,
+                ..HighlightingOptions::default()
+            },
+        }
+    }
+
+    /// Dockerfiles: instructions are keywords, but only where they start a
+    /// line, since words like `run` or `copy` also turn up in commands.
+    fn dockerfile() -> Self {
+        Self {
+            name: "Dockerfile".into(),
+            indent_style: None,
+            tab_width: None,
+            hl_opts: HighlightingOptions {
+                numbers: true,
+                string_delimiters: vec!['"', '\''],
+                comment_leader: Some("#".into()),
+                variables: true,
+                primary_keywords: owned(&[
+                    "FROM",
+                    "RUN",
+                    "CMD",
+                    "LABEL",
+                    "MAINTAINER",
+                    "EXPOSE",
+                    "ENV",
+                    "ADD",
+                    "COPY",
+                    "ENTRYPOINT",
+                    "VOLUME",
+                    "USER",
+                    "WORKDIR",
+                    "ARG",
+                    "ONBUILD",
+                    "STOPSIGNAL",
+                    "HEALTHCHECK",
+                    "SHELL",
+                ]),
+                keywords_case_insensitive: true,
+                keywords_at_line_start: true,
+                ..HighlightingOptions::default()
+            },
+        }
+    }
+
+    /// Makefiles, whose recipe lines must start with a tab.
+    fn makefile() -> Self {
+        Self {
+            name: "Makefile".into(),
+            indent_style: Some(IndentStyle::Tabs),
+            tab_width: None,
+            hl_opts: HighlightingOptions {
+                comment_leader: Some("#".into()),
+                variables: true,
+                make_variables: true,
+                targets: true,
+                primary_keywords: owned(&[
+                    "include", "-include", "sinclude", "ifeq", "ifneq",
+                    "ifdef", "ifndef", "else", "endif", "define", "endef",
+                    "export", "unexport", "override", "private", "vpath",
+                ]),
+                ..HighlightingOptions::default()
+            },
+        }
+    }
+
+    /// Ruby. String interpolation is highlighted as part of the string.
+    fn ruby() -> Self {
+        Self {
+            name: "Ruby".into(),
+            indent_style: Some(IndentStyle::Spaces(2)),
+            tab_width: None,
+            hl_opts: HighlightingOptions {
+                numbers: true,
+                string_delimiters: vec!['"'],
+                raw_string_delimiters: vec!['\''],
+                comment_leader: Some("#".into()),
+                multiline_comment: Some(("=begin".into(), "=end".into())),
+                multiline_comment_at_line_start: true,
+                symbols: true,
+                primary_keywords: owned(&[
+                    "alias", "and", "begin", "break", "case", "class", "def",
+                    "do", "else", "elsif", "end", "ensure", "false", "for",
+                    "if", "in", "module", "next", "nil", "not", "or", "redo",
+                    "rescue", "retry", "return", "self", "super", "then",
+                    "true", "undef", "unless", "until", "when", "while",
+                    "yield",
+                ]),
+                secondary_keywords: owned(&[
+                    "puts",
+                    "print",
+                    "require",
+                    "require_relative",
+                    "include",
+                    "extend",
+                    "attr_reader",
+                    "attr_writer",
+                    "attr_accessor",
+                    "private",
+                    "protected",
+                    "public",
+                    "raise",
+                    "lambda",
+                    "proc",
+                    "loop",
+                ]),
+                ..HighlightingOptions::default()
+            },
+        }
+    }
+
+    /// JavaScript, or a dialect of it that adds `types` as secondary
+    /// keywords. Template literals are highlighted as strings up to the end
+    /// of their line, and regex literals aren't recognized.
+    fn javascript(name: &str, types: &[&str]) -> Self {
+        Self {
+            name: name.into(),
+            indent_style: Some(IndentStyle::Spaces(2)),
+            tab_width: None,
+            hl_opts: HighlightingOptions {
+                numbers: true,
+                string_delimiters: vec!['"', '\'', '`'],
+                comment_leader: Some("//".into()),
+                multiline_comment: Some(("/*".into(), "*/".into())),
+                primary_keywords: owned(&[
+                    "async",
+                    "await",
+                    "break",
+                    "case",
+                    "catch",
+                    "class",
+                    "const",
+                    "continue",
+                    "debugger",
+                    "default",
+                    "delete",
+                    "do",
+                    "else",
+                    "export",
+                    "extends",
+                    "finally",
+                    "for",
+                    "from",
+                    "function",
+                    "if",
+                    "import",
+                    "in",
+                    "instanceof",
+                    "let",
+                    "new",
+                    "of",
+                    "return",
+                    "static",
+                    "super",
+                    "switch",
+                    "this",
+                    "throw",
+                    "try",
+                    "typeof",
+                    "var",
+                    "void",
+                    "while",
+                    "with",
+                    "yield",
+                    "true",
+                    "false",
+                    "null",
+                    "undefined",
+                ]),
+                secondary_keywords: owned(types),
+                operators: owned(&[
+                    "+", "-", "*", "/", "%", "**", "++", "--", "=", "+=", "-=",
+                    "*=", "/=", "%=", "**=", "==", "!=", "===", "!==", "<",
+                    ">", "<=", ">=", "&", "|", "^", "~", "<<", ">>", ">>>",
+                    "&=", "|=", "^=", "<<=", ">>=", ">>>=", "&&", "||", "??",
+                    "&&=", "||=", "??=", "!", "?", "?.", "=>", "...",
+                ]),
+                ..HighlightingOptions::default()
+            },
+        }
+    }
+
+    fn typescript() -> Self {
+        Self::javascript(
+            "TypeScript",
+            &[
+                "any",
+                "unknown",
+                "never",
+                "string",
+                "number",
+                "boolean",
+                "symbol",
+                "bigint",
+                "object",
+                "keyof",
+                "declare",
+                "type",
+                "enum",
+                "interface",
+                "namespace",
+                "implements",
+                "readonly",
+                "private",
+                "public",
+                "protected",
+                "abstract",
+            ],
+        )
+    }
+
+    fn shell() -> Self {
+        Self {
+            name: "Shell".into(),
+            indent_style: None,
+            tab_width: None,
+            hl_opts: HighlightingOptions {
+                numbers: true,
+                string_delimiters: vec!['"'],
+                raw_string_delimiters: vec!['\''],
+                comment_leader: Some("#".into()),
+                variables: true,
+                primary_keywords: owned(&[
+                    "if", "then", "else", "elif", "fi", "for", "while",
+                    "until", "do", "done", "case", "esac", "in", "function",
+                    "select", "time", "return", "break", "continue", "local",
+                    "export", "readonly", "declare", "unset", "shift", "exit",
+                ]),
+                secondary_keywords: owned(&[
+                    "echo", "printf", "read", "cd", "pwd", "test", "eval",
+                    "exec", "source", "trap", "set", "alias", "true", "false",
+                ]),
+                ..HighlightingOptions::default()
+            },
         }
     }
 }
 
-impl<'a> FileType<'a> {
+impl FileType {
     #[inline]
     pub(crate) fn name(&self) -> String {
         self.name.clone()
@@ -67,17 +665,27 @@ impl<'a> FileType<'a> {
     pub(crate) const fn highlighting_options(&self) -> &HighlightingOptions {
         &self.hl_opts
     }
+
+    #[inline]
+    pub const fn indent_style(&self) -> Option<IndentStyle> {
+        self.indent_style
+    }
+
+    #[inline]
+    pub const fn tab_width(&self) -> Option<usize> {
+        self.tab_width
+    }
 }
 
-impl<'a> HighlightingOptions<'a> {
+impl HighlightingOptions {
     #[inline]
     pub(crate) const fn numbers(&self) -> bool {
         self.numbers
     }
 
     #[inline]
-    pub(crate) const fn strings(&self) -> bool {
-        self.strings
+    pub(crate) fn string_delimiters(&self) -> &[char] {
+        &self.string_delimiters
     }
 
     #[inline]
@@ -86,22 +694,152 @@ impl<'a> HighlightingOptions<'a> {
     }
 
     #[inline]
-    pub(crate) const fn comments(&self) -> bool {
-        self.comments
+    pub(crate) fn raw_string_delimiters(&self) -> &[char] {
+        &self.raw_string_delimiters
+    }
+
+    #[inline]
+    pub(crate) fn long_string(&self) -> Option<(&str, &str)> {
+        self.long_string
+            .as_ref()
+            .map(|(open, close)| (open.as_str(), close.as_str()))
+    }
+
+    #[inline]
+    pub(crate) fn comment_leader(&self) -> Option<&str> {
+        self.comment_leader.as_deref()
+    }
+
+    #[inline]
+    pub(crate) const fn variables(&self) -> bool {
+        self.variables
+    }
+
+    #[inline]
+    pub(crate) const fn make_variables(&self) -> bool {
+        self.make_variables
+    }
+
+    #[inline]
+    pub(crate) const fn targets(&self) -> bool {
+        self.targets
+    }
+
+    #[inline]
+    pub(crate) const fn keywords_at_line_start(&self) -> bool {
+        self.keywords_at_line_start
+    }
+
+    #[inline]
+    pub(crate) fn primary_keywords(&self) -> &[String] {
+        &self.primary_keywords
+    }
+
+    #[inline]
+    pub(crate) fn secondary_keywords(&self) -> &[String] {
+        &self.secondary_keywords
+    }
+
+    #[inline]
+    pub(crate) const fn keywords_case_insensitive(&self) -> bool {
+        self.keywords_case_insensitive
+    }
+
+    #[inline]
+    pub(crate) fn operators(&self) -> &[String] {
+        &self.operators
+    }
+
+    #[inline]
+    pub(crate) fn multiline_comment(&self) -> Option<(&str, &str)> {
+        self.multiline_comment
+            .as_ref()
+            .map(|(open, close)| (open.as_str(), close.as_str()))
+    }
+
+    #[inline]
+    pub(crate) const fn multiline_comment_at_line_start(&self) -> bool {
+        self.multiline_comment_at_line_start
     }
 
     #[inline]
-    pub(crate) const fn primary_keywords(&self) -> &[&str] {
-        self.primary_keywords
+    pub(crate) const fn symbols(&self) -> bool {
+        self.symbols
     }
 
     #[inline]
-    pub(crate) const fn secondary_keywords(&self) -> &[&str] {
-        self.secondary_keywords
+    pub(crate) const fn key_values(&self) -> bool {
+        self.key_values
+    }
+
+    /// The comment leader followed by the extra ones.
+    pub(crate) fn comment_leaders(&self) -> impl Iterator<Item = &str> {
+        self.comment_leader
+            .iter()
+            .chain(&self.extra_comment_leaders)
+            .map(String::as_str)
     }
 
     #[inline]
-    pub(crate) const fn multiline_comments(&self) -> bool {
-        self.multiline_comments
+    pub(crate) const fn tags(&self) -> bool {
+        self.tags
+    }
+}
+
+fn owned(words: &[&str]) -> Vec<String> {
+    words.iter().map(|word| (*word).to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name_of(file: &str) -> String {
+        FileType::from(file).name()
+    }
+
+    #[test]
+    fn dockerfiles_and_makefiles() {
+        for file in &[
+            "Dockerfile",
+            "Containerfile",
+            "x/Dockerfile.dev",
+            "app.dockerfile",
+        ] {
+            assert_eq!(name_of(file), "Dockerfile", "{}", file);
+        }
+        for file in &["Makefile", "makefile", "GNUmakefile", "rules.mk"] {
+            assert_eq!(name_of(file), "Makefile", "{}", file);
+        }
+        assert_eq!(name_of("CMakeLists.txt"), "No filetype");
+    }
+
+    #[test]
+    fn names_beat_globs_beat_extensions() {
+        assert_eq!(name_of("a/b/main.RS"), "Rust");
+        assert_eq!(name_of(".gitignore"), "No filetype");
+
+        FileType::register(
+            &["*.mk", "CMakeLists.txt"],
+            "CMake",
+            HighlightingOptions::default(),
+            None,
+            None,
+        );
+        assert_eq!(name_of("foo.mk"), "CMake");
+        assert_eq!(name_of("Makefile"), "Makefile");
+        assert_eq!(name_of("CMakeLists.txt"), "CMake");
+
+        FileType::register(
+            &["*.txt", "notes-?.md"],
+            "Text",
+            HighlightingOptions::default(),
+            None,
+            None,
+        );
+        assert_eq!(name_of("CMakeLists.txt"), "CMake");
+        assert_eq!(name_of("a.txt"), "Text");
+        assert_eq!(name_of("notes-1.md"), "Text");
+        assert_eq!(name_of("notes-12.md"), "No filetype");
     }
 }
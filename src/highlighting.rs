@@ -1,34 +1,97 @@
-use crate::{highlighting, HighlightingOptions};
+use crate::{highlighting, Diagnostic, HighlightingOptions};
 use termion::color;
 
-#[derive(PartialEq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum Type {
     None,
     Number,
     Match,
     String,
+    Escape,
     Character,
     Comment,
     MultilineComment,
     PrimaryKeyword,
+    Variable,
+    Symbol,
+    Tag,
+    Attribute,
+    Entity,
     SecondaryKeyword,
+    Operator,
+    Warning,
+    Error,
 }
 
 impl Type {
-    pub(crate) fn to_color(&self) -> impl color::Color {
+    pub(crate) const ALL: [Self; 18] = [
+        Type::None,
+        Type::Number,
+        Type::Match,
+        Type::String,
+        Type::Escape,
+        Type::Character,
+        Type::Comment,
+        Type::MultilineComment,
+        Type::PrimaryKeyword,
+        Type::Variable,
+        Type::Symbol,
+        Type::Tag,
+        Type::Attribute,
+        Type::Entity,
+        Type::SecondaryKeyword,
+        Type::Operator,
+        Type::Warning,
+        Type::Error,
+    ];
+
+    pub(crate) const fn to_color(self) -> color::Rgb {
         match self {
             Type::Number => color::Rgb(220, 163, 163),
             Type::Match => color::Rgb(38, 139, 210),
             Type::String => color::Rgb(211, 54, 130),
+            Type::Escape | Type::Entity => color::Rgb(203, 75, 22),
             Type::Character => color::Rgb(108, 113, 196),
             Type::Comment | Type::MultilineComment => color::Rgb(133, 153, 0),
             Type::PrimaryKeyword => color::Rgb(181, 137, 0),
             Type::SecondaryKeyword => color::Rgb(42, 161, 152),
-            _ => color::Rgb(255, 255, 255),
+            Type::Variable => color::Rgb(156, 220, 254),
+            Type::Symbol => color::Rgb(197, 134, 192),
+            Type::Tag => color::Rgb(86, 156, 214),
+            Type::Attribute => color::Rgb(181, 206, 168),
+            Type::Operator => color::Rgb(147, 161, 161),
+            Type::Warning => color::Rgb(255, 199, 0),
+            Type::Error => color::Rgb(220, 50, 47),
+            Type::None => color::Rgb(255, 255, 255),
         }
     }
 
-    pub(crate) fn fg_string(&self) -> String {
+    /// The class marking text of this type in exported HTML, or `None` for
+    /// plain text.
+    pub(crate) const fn css_class(self) -> Option<&'static str> {
+        match self {
+            Type::None => None,
+            Type::Number => Some("hl-number"),
+            Type::Match => Some("hl-match"),
+            Type::String => Some("hl-string"),
+            Type::Escape => Some("hl-escape"),
+            Type::Character => Some("hl-character"),
+            Type::Comment => Some("hl-comment"),
+            Type::MultilineComment => Some("hl-multiline-comment"),
+            Type::PrimaryKeyword => Some("hl-keyword"),
+            Type::SecondaryKeyword => Some("hl-secondary-keyword"),
+            Type::Variable => Some("hl-variable"),
+            Type::Symbol => Some("hl-symbol"),
+            Type::Tag => Some("hl-tag"),
+            Type::Attribute => Some("hl-attribute"),
+            Type::Entity => Some("hl-entity"),
+            Type::Operator => Some("hl-operator"),
+            Type::Warning => Some("hl-warning"),
+            Type::Error => Some("hl-error"),
+        }
+    }
+
+    pub(crate) fn fg_string(self) -> String {
         format!("{}", termion::color::Fg(self.to_color()))
     }
 }
@@ -38,11 +101,14 @@ pub trait Highlighter {
         &mut self,
         opts: &HighlightingOptions,
         word: Option<&str>,
+        diagnostics: &[Diagnostic],
         start_with_comment: bool,
     ) -> bool;
 
     fn highlight_match(&mut self, word: Option<&str>);
 
+    fn highlight_diagnostics(&mut self, diagnostics: &[Diagnostic]);
+
     fn highlight_char(
         &mut self,
         index: &mut usize,
@@ -51,6 +117,14 @@ pub trait Highlighter {
         chars: &[char],
     ) -> bool;
 
+    fn highlight_key_value(
+        &mut self,
+        opts: &HighlightingOptions,
+        chars: &[char],
+    );
+
+    fn highlight_target(&mut self, chars: &[char]) -> usize;
+
     fn highlight_comment(
         &mut self,
         index: &mut usize,
@@ -67,6 +141,48 @@ pub trait Highlighter {
         chars: &[char],
     ) -> bool;
 
+    fn highlight_long_string(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool;
+
+    fn highlight_escape(&mut self, index: &mut usize, chars: &[char]) -> bool;
+
+    fn highlight_variable(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool;
+
+    fn highlight_symbol(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool;
+
+    fn highlight_tag(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool;
+
+    fn highlight_entity(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool;
+
     fn highlight_number(
         &mut self,
         index: &mut usize,
@@ -101,10 +217,18 @@ pub trait Highlighter {
         &mut self,
         index: &mut usize,
         chars: &[char],
-        keywords: &[&str],
+        keywords: &[String],
+        ignore_case: bool,
         hl_type: highlighting::Type,
     ) -> bool;
 
+    fn highlight_operators(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        chars: &[char],
+    ) -> bool;
+
     fn highlight_multiline_comment(
         &mut self,
         index: &mut usize,
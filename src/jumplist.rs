@@ -0,0 +1,68 @@
+use crate::Position;
+
+/// How many positions a `JumpList` remembers by default.
+const DEFAULT_CAPACITY: usize = 100;
+
+/// The positions the cursor jumped away from, to retrace them like a
+/// browser's history.
+pub struct JumpList {
+    positions: Vec<Position>,
+    /// Index of the entry `back` and `forward` last returned, or
+    /// `positions.len()` if the cursor has moved on since.
+    current: usize,
+    capacity: usize,
+}
+
+impl Default for JumpList {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+}
+
+impl JumpList {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            positions: Vec::new(),
+            current: 0,
+            capacity,
+        }
+    }
+
+    /// Records `position` as a place jumped away from. Anything that was
+    /// stepped back over is forgotten, and the oldest entry goes once the
+    /// list is full.
+    pub fn push(&mut self, position: Position) {
+        self.positions.truncate(self.current);
+        if self.positions.last() != Some(&position) {
+            self.positions.push(position);
+        }
+        if self.positions.len() > self.capacity {
+            self.positions.remove(0);
+        }
+        self.current = self.positions.len();
+    }
+
+    /// Steps back to the previous position. `from` is where the cursor is
+    /// now, which is remembered so that `forward` can return to it.
+    pub fn back(&mut self, from: &Position) -> Option<Position> {
+        if self.current == self.positions.len() {
+            self.push(from.clone());
+            self.current = self.positions.len().saturating_sub(1);
+        }
+        self.current = self.current.checked_sub(1)?;
+        self.positions.get(self.current).cloned()
+    }
+
+    /// Steps forward again after `back`.
+    pub fn forward(&mut self) -> Option<Position> {
+        let next = self.current.saturating_add(1);
+        let position = self.positions.get(next).cloned()?;
+        self.current = next;
+        Some(position)
+    }
+
+    pub fn clear(&mut self) {
+        self.positions.clear();
+        self.current = 0;
+    }
+}
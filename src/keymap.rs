@@ -0,0 +1,273 @@
+use serde::{
+    de::{value, IntoDeserializer},
+    Deserialize,
+};
+use termion::event::Key;
+
+use std::collections::HashMap;
+
+/// Everything the editor can be asked to do from the keyboard. The `[keys]`
+/// table of the config names them in snake case, as in `move_line_up`.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    #[serde(skip)]
+    InsertChar(char),
+    InsertIndent,
+    InsertTimestamp,
+    Delete,
+    Backspace,
+    DeleteWordBackward,
+    Save,
+    OpenFile,
+    OpenRecent,
+    Search,
+    /// Searches all the files under the working directory.
+    ProjectSearch,
+    JumpBack,
+    JumpForward,
+    HighlightWord,
+    ToggleComment,
+    MoveLineUp,
+    MoveLineDown,
+    /// Moves to the next line indented as much as the cursor's line.
+    NextAtIndent,
+    PreviousAtIndent,
+    DuplicateLine,
+    ToggleFold,
+    Sanitize,
+    /// Removes repeated lines. Only offered in the command palette.
+    DedupLines,
+    /// Converts indentation to the indent style. Only offered in the
+    /// command palette.
+    Retab,
+    /// Adds or drops the byte order mark. Only offered in the command
+    /// palette.
+    ToggleBom,
+    Check,
+    Reload,
+    ToggleLineNumbers,
+    ToggleWhitespace,
+    ToggleRuler,
+    ToggleCurrentLine,
+    CommandPalette,
+    /// Starts or stops recording the actions performed into a macro.
+    ToggleRecording,
+    ReplayMacro,
+    NextBuffer,
+    PreviousBuffer,
+    CloseBuffer,
+    Quit,
+    /// Quits without asking, even with unsaved changes.
+    ForceQuit,
+}
+
+/// Maps keys to the actions they trigger.
+///
+/// Printable characters that aren't bound to anything are inserted into the
+/// document, so they don't need entries of their own.
+pub struct KeyMap {
+    bindings: HashMap<Key, Action>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        let mut keymap = Self {
+            bindings: HashMap::new(),
+        };
+        keymap.bind(Key::Up, Action::MoveUp);
+        keymap.bind(Key::Down, Action::MoveDown);
+        keymap.bind(Key::Left, Action::MoveLeft);
+        keymap.bind(Key::Right, Action::MoveRight);
+        keymap.bind(Key::PageUp, Action::PageUp);
+        keymap.bind(Key::PageDown, Action::PageDown);
+        keymap.bind(Key::Home, Action::Home);
+        keymap.bind(Key::End, Action::End);
+        keymap.bind(Key::Char('\t'), Action::InsertIndent);
+        keymap.bind(Key::Delete, Action::Delete);
+        keymap.bind(Key::Backspace, Action::Backspace);
+        keymap.bind(Key::Ctrl('w'), Action::DeleteWordBackward);
+        keymap.bind(Key::Ctrl('s'), Action::Save);
+        keymap.bind(Key::Ctrl('o'), Action::OpenFile);
+        keymap.bind(Key::Alt('r'), Action::OpenRecent);
+        keymap.bind(Key::Ctrl('f'), Action::Search);
+        keymap.bind(Key::Alt('g'), Action::ProjectSearch);
+        // Ctrl-O opens files and Ctrl-I is indistinguishable from Tab, so
+        // jumps use Alt instead.
+        keymap.bind(Key::Alt('o'), Action::JumpBack);
+        keymap.bind(Key::Alt('i'), Action::JumpForward);
+        keymap.bind(Key::Ctrl('n'), Action::HighlightWord);
+        // Terminals send Ctrl-/ as Ctrl-_, which termion reports as Ctrl-7.
+        keymap.bind(Key::Ctrl('7'), Action::ToggleComment);
+        // termion doesn't report Alt with the arrow keys, so lines move
+        // with Alt-k and Alt-j instead.
+        keymap.bind(Key::Alt('k'), Action::MoveLineUp);
+        keymap.bind(Key::Alt('j'), Action::MoveLineDown);
+        // Alt-[ starts the terminal's escape sequences, so these take the
+        // braces instead of the brackets.
+        keymap.bind(Key::Alt('}'), Action::NextAtIndent);
+        keymap.bind(Key::Alt('{'), Action::PreviousAtIndent);
+        keymap.bind(Key::Ctrl('d'), Action::DuplicateLine);
+        keymap.bind(Key::Alt('f'), Action::ToggleFold);
+        keymap.bind(Key::Alt('s'), Action::Sanitize);
+        keymap.bind(Key::Alt('t'), Action::InsertTimestamp);
+        keymap.bind(Key::Alt('c'), Action::Check);
+        keymap.bind(Key::Ctrl('r'), Action::Reload);
+        keymap.bind(Key::Ctrl('l'), Action::ToggleLineNumbers);
+        keymap.bind(Key::Alt('w'), Action::ToggleWhitespace);
+        keymap.bind(Key::Alt('u'), Action::ToggleRuler);
+        keymap.bind(Key::Alt('l'), Action::ToggleCurrentLine);
+        // Terminals can't tell Ctrl-Shift-P from Ctrl-P.
+        keymap.bind(Key::Ctrl('p'), Action::CommandPalette);
+        keymap.bind(Key::Alt('m'), Action::ToggleRecording);
+        keymap.bind(Key::Alt('p'), Action::ReplayMacro);
+        keymap.bind(Key::Alt('.'), Action::NextBuffer);
+        keymap.bind(Key::Alt(','), Action::PreviousBuffer);
+        keymap.bind(Key::Alt('x'), Action::CloseBuffer);
+        keymap.bind(Key::Ctrl('q'), Action::Quit);
+        keymap.bind(Key::Alt('q'), Action::ForceQuit);
+        keymap
+    }
+}
+
+impl KeyMap {
+    /// Binds `key` to `action`, replacing any previous binding of `key`.
+    pub fn bind(&mut self, key: Key, action: Action) {
+        self.bindings.insert(key, action);
+    }
+
+    pub fn unbind(&mut self, key: Key) {
+        self.bindings.remove(&key);
+    }
+
+    /// Binds `key` to `action`, or unbinds it if `action` is `None`.
+    pub fn set(&mut self, key: Key, action: Option<Action>) {
+        match action {
+            Some(action) => self.bind(key, action),
+            None => self.unbind(key),
+        }
+    }
+
+    /// The action `key` triggers, falling back to inserting unbound
+    /// characters.
+    pub fn action(&self, key: Key) -> Option<Action> {
+        match (self.bindings.get(&key), key) {
+            (Some(action), _) => Some(*action),
+            (None, Key::Char(c)) => Some(Action::InsertChar(c)),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a key as the config names it: a single character such as `x`,
+/// `ctrl-` or `alt-` followed by one, `f1` to `f12`, or one of `up`,
+/// `down`, `left`, `right`, `home`, `end`, `pageup`, `pagedown`,
+/// `backspace`, `delete`, `insert`, `tab`, `backtab`, `enter` and `esc`.
+pub fn parse_key(name: &str) -> Option<Key> {
+    if let Some(c) = name.strip_prefix("ctrl-").and_then(single_char) {
+        return Some(Key::Ctrl(c));
+    }
+    if let Some(c) = name.strip_prefix("alt-").and_then(single_char) {
+        return Some(Key::Alt(c));
+    }
+    if let Some(c) = single_char(name) {
+        return Some(Key::Char(c));
+    }
+    let key = match name {
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "backspace" => Key::Backspace,
+        "delete" => Key::Delete,
+        "insert" => Key::Insert,
+        "tab" => Key::Char('\t'),
+        "backtab" => Key::BackTab,
+        "enter" => Key::Char('\n'),
+        "esc" => Key::Esc,
+        _ => {
+            let number: u8 = name.strip_prefix('f')?.parse().ok()?;
+            if !(1..=12).contains(&number) {
+                return None;
+            }
+            Key::F(number)
+        },
+    };
+    Some(key)
+}
+
+/// Parses an action by its snake case name, or `none`, which leaves a key
+/// unbound. Returns `None` for an unknown name.
+pub fn parse_action(name: &str) -> Option<Option<Action>> {
+    if name == "none" {
+        return Some(None);
+    }
+    let deserializer: value::StrDeserializer<value::Error> =
+        name.into_deserializer();
+    Action::deserialize(deserializer).ok().map(Some)
+}
+
+fn single_char(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_names() {
+        assert_eq!(parse_key("ctrl-s"), Some(Key::Ctrl('s')));
+        assert_eq!(parse_key("alt-}"), Some(Key::Alt('}')));
+        assert_eq!(parse_key("x"), Some(Key::Char('x')));
+        assert_eq!(parse_key("-"), Some(Key::Char('-')));
+        assert_eq!(parse_key("pagedown"), Some(Key::PageDown));
+        assert_eq!(parse_key("f12"), Some(Key::F(12)));
+        assert_eq!(parse_key("f13"), None);
+        assert_eq!(parse_key("ctrl-"), None);
+        assert_eq!(parse_key("ctrl-ss"), None);
+        assert_eq!(parse_key(""), None);
+    }
+
+    #[test]
+    fn action_names() {
+        assert_eq!(parse_action("save"), Some(Some(Action::Save)));
+        assert_eq!(
+            parse_action("move_line_up"),
+            Some(Some(Action::MoveLineUp))
+        );
+        assert_eq!(parse_action("none"), Some(None));
+        assert_eq!(parse_action("insert_char"), None);
+        assert_eq!(parse_action("Save"), None);
+    }
+
+    #[test]
+    fn set_overrides_and_unbinds() {
+        let mut keymap = KeyMap::default();
+        keymap.set(Key::Ctrl('s'), None);
+        keymap.set(Key::F(2), Some(Action::Save));
+        assert_eq!(keymap.action(Key::Ctrl('s')), None);
+        assert_eq!(keymap.action(Key::F(2)), Some(Action::Save));
+        keymap.set(Key::Char('x'), None);
+        assert_eq!(
+            keymap.action(Key::Char('x')),
+            Some(Action::InsertChar('x'))
+        );
+    }
+}
@@ -9,24 +9,115 @@
     clippy::must_use_candidate,
     clippy::missing_errors_doc
 )]
+mod buffers;
+mod command;
+mod config;
+mod diagnostic;
 mod document;
 mod editor;
+mod encoding;
+mod export;
 mod filetype;
 mod highlighting;
+mod jumplist;
+mod keymap;
+mod picker;
+mod project_search;
+mod recent;
 mod row;
+mod selection;
 mod terminal;
+mod view;
 
-pub use document::Document;
+pub use buffers::{Buffer, BufferList};
+pub use command::{Command, CommandRegistry};
+pub use config::{Config, ConfigError};
+pub use diagnostic::{cargo_diagnostics, Diagnostic, Severity};
+pub use document::{
+    BufferKind, DocStats, Document, DocumentError, FinalNewline, IndentStyle,
+    SaveOptions,
+};
 use editor::Editor;
-pub use editor::{Position, SearchDirection};
+pub use editor::{LineNumbers, Position, SearchDirection};
+pub use encoding::FileEncoding;
+pub use export::{html_css, to_html};
 pub use filetype::{FileType, HighlightingOptions};
 pub use highlighting::Highlighter;
+pub use jumplist::JumpList;
+pub use keymap::{parse_action, parse_key, Action, KeyMap};
+pub use picker::FilePicker;
+pub use project_search::search_files;
+pub use recent::RecentFiles;
 pub use row::Row;
-pub use terminal::Terminal;
+pub use selection::{BlockSelection, Selection};
+pub use terminal::{Bell, Size, Terminal};
+pub use view::ViewState;
 
 use anyhow::Result;
+use std::{env, path::Path};
 
 fn main() -> Result<()> {
-    Editor::new()?.run();
-    Ok(())
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (file_name, position) = parse_args(&args);
+    Terminal::with_panic_guard(|| {
+        Editor::new(file_name, &position)?.run();
+        Ok(())
+    })
+}
+
+/// Picks the file to open and the initial cursor position out of the command
+/// line. Accepts `FILE`, `FILE:LINE`, `FILE:LINE:COLUMN` and `+LINE FILE`,
+/// with lines and columns counted from 1.
+fn parse_args(args: &[String]) -> (Option<&str>, Position) {
+    let mut file_name = None;
+    let mut line = None;
+    let mut column = None;
+
+    for arg in args {
+        if let Some(number) = arg.strip_prefix('+') {
+            if let Ok(number) = number.parse() {
+                line = Some(number);
+                continue;
+            }
+        }
+        if file_name.is_none() {
+            let (name, file_line, file_column) = split_location(arg);
+            file_name = Some(name);
+            line = file_line.or(line);
+            column = file_column;
+        }
+    }
+
+    let position = Position::new(
+        column.unwrap_or(1).saturating_sub(1),
+        line.unwrap_or(1).saturating_sub(1),
+    );
+    (file_name, position)
+}
+
+/// Splits a trailing `:LINE` or `:LINE:COLUMN` off `arg`, unless `arg` names
+/// an existing file as it is.
+fn split_location(arg: &str) -> (&str, Option<usize>, Option<usize>) {
+    if Path::new(arg).exists() {
+        return (arg, None, None);
+    }
+    let number = |s: &str| s.parse::<usize>().ok();
+
+    let mut parts = arg.rsplitn(3, ':');
+    if let (Some(last), Some(middle), Some(name)) =
+        (parts.next(), parts.next(), parts.next())
+    {
+        if let (Some(line), Some(column)) = (number(middle), number(last)) {
+            return (name, Some(line), Some(column));
+        }
+    }
+
+    let mut parts = arg.rsplitn(2, ':');
+    if let (Some(last), Some(name)) = (parts.next(), parts.next()) {
+        if let Some(line) = number(last) {
+            return (name, Some(line), None);
+        }
+    }
+
+    (arg, None, None)
 }
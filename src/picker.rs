@@ -0,0 +1,249 @@
+use std::{
+    cmp::Reverse,
+    fs, iter,
+    path::{Path, PathBuf},
+};
+
+/// Stop collecting files after this many, so that opening the picker in a
+/// huge tree stays quick.
+const MAX_FILES: usize = 10_000;
+
+/// A list of files, ranked against a query by fuzzy matching.
+pub struct FilePicker {
+    files: Vec<PathBuf>,
+}
+
+impl FilePicker {
+    /// Collects the files under `root`, at most `max_depth` directories
+    /// deep. Hidden files and directories, `.git` among them, are skipped,
+    /// as are entries that can't be read.
+    pub fn new(root: &Path, max_depth: usize) -> Self {
+        let mut files = files_under(root, max_depth);
+        files.sort();
+        files.sort_by_key(|file| file.as_os_str().len());
+        Self { files }
+    }
+
+    /// Picks from `files`. Files that match a query equally well keep the
+    /// order they are given in.
+    pub fn from_files(files: Vec<PathBuf>) -> Self {
+        Self { files }
+    }
+
+    /// The files matching `query`, best match first, or shortest first for
+    /// files under a directory. Every file matches an empty query.
+    pub fn matches(&self, query: &str) -> Vec<PathBuf> {
+        let mut scored: Vec<(usize, &PathBuf)> = self
+            .files
+            .iter()
+            .filter_map(|file| {
+                let candidate = file.to_str()?;
+                Some((score(query, candidate)?, file))
+            })
+            .collect();
+        scored.sort_by_key(|&(score, _)| Reverse(score));
+        scored.into_iter().map(|(_, file)| file.clone()).collect()
+    }
+}
+
+/// The files under `root`, relative to it, at most `max_depth` directories
+/// deep. Hidden files and directories, `.git` among them, are skipped, as
+/// are entries that can't be read and those matching a pattern in
+/// `root/.gitignore`.
+pub(crate) fn files_under(root: &Path, max_depth: usize) -> Vec<PathBuf> {
+    let ignored = fs::read_to_string(root.join(".gitignore"))
+        .map(|gitignore| ignore_patterns(&gitignore))
+        .unwrap_or_default();
+    let mut files = Vec::new();
+    walk(root, root, max_depth, &ignored, &mut files);
+    files
+}
+
+/// The patterns of a `.gitignore` file that name files or directories
+/// anywhere in the tree, like `target/` or `*.log`. Negated patterns and
+/// those with a path in them aren't supported and are left out.
+fn ignore_patterns(gitignore: &str) -> Vec<String> {
+    gitignore
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(&['#', '!'][..]))
+        .map(|line| line.trim_start_matches('/').trim_end_matches('/'))
+        .filter(|pattern| !pattern.is_empty() && !pattern.contains('/'))
+        .map(String::from)
+        .collect()
+}
+
+/// Whether `name` matches the glob `pattern`, where `*` stands for any run
+/// of chars and `?` for any one char.
+pub(crate) fn glob_matches(pattern: &str, name: &str) -> bool {
+    let mut pattern_chars = pattern.chars();
+    match pattern_chars.next() {
+        None => name.is_empty(),
+        Some('*') => name
+            .char_indices()
+            .map(|(skip, _)| skip)
+            .chain(iter::once(name.len()))
+            .any(|skip| {
+                name.get(skip..).map_or(false, |name| {
+                    glob_matches(pattern_chars.as_str(), name)
+                })
+            }),
+        Some(p) => {
+            let mut name_chars = name.chars();
+            match name_chars.next() {
+                Some(c) if p == '?' || p == c => {
+                    glob_matches(pattern_chars.as_str(), name_chars.as_str())
+                },
+                _ => false,
+            }
+        },
+    }
+}
+
+fn walk(
+    root: &Path,
+    dir: &Path,
+    depth: usize,
+    ignored: &[String],
+    files: &mut Vec<PathBuf>,
+) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        if files.len() >= MAX_FILES {
+            return;
+        }
+        let skip = entry.file_name().to_str().map_or(true, |name| {
+            name.starts_with('.')
+                || ignored.iter().any(|pattern| glob_matches(pattern, name))
+        });
+        if skip {
+            continue;
+        }
+        let path = entry.path();
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(_) => continue,
+        };
+
+        if file_type.is_dir() {
+            if depth > 0 {
+                walk(root, &path, depth.saturating_sub(1), ignored, files);
+            }
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            files.push(relative.to_path_buf());
+        }
+    }
+}
+
+/// How well `candidate` matches `query`, or `None` if it doesn't contain the
+/// chars of `query` in order. Case is ignored. Matches that run on from the
+/// previous one, start a word, or fall in the file name rather than the
+/// directories score higher.
+#[allow(clippy::arithmetic_side_effects)]
+pub(crate) fn score(query: &str, candidate: &str) -> Option<usize> {
+    let file_name_start = candidate.rfind('/').map_or(0, |slash| slash + 1);
+    let mut query = query.chars().flat_map(char::to_lowercase).peekable();
+    let mut score = 0;
+    let mut previous: Option<char> = None;
+    let mut previous_matched = false;
+
+    for (byte_index, c) in candidate.char_indices() {
+        let wanted = match query.peek() {
+            Some(&wanted) => wanted,
+            None => break,
+        };
+        let matched = c.to_lowercase().eq(Some(wanted));
+
+        if matched {
+            query.next();
+            score += 1;
+            if previous_matched {
+                score += 5;
+            }
+            if previous
+                .map_or(true, |p| matches!(p, '/' | '_' | '-' | '.' | ' '))
+            {
+                score += 8;
+            }
+            if byte_index >= file_name_start {
+                score += 2;
+            }
+        }
+        previous_matched = matched;
+        previous = Some(c);
+    }
+
+    if query.peek().is_some() {
+        return None;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, process};
+
+    #[test]
+    fn prefix_and_consecutive_matches_rank_first() {
+        let picker = FilePicker::from_files(vec![
+            PathBuf::from("src/exdxixtxoxr.rs"),
+            PathBuf::from("src/xeditor.rs"),
+            PathBuf::from("src/editor.rs"),
+        ]);
+        assert_eq!(
+            picker.matches("editor"),
+            vec![
+                PathBuf::from("src/editor.rs"),
+                PathBuf::from("src/xeditor.rs"),
+                PathBuf::from("src/exdxixtxoxr.rs"),
+            ]
+        );
+        assert!(score("ed", "editor/a.rs") < score("ed", "a/editor.rs"));
+        assert_eq!(score("xyz", "src/editor.rs"), None);
+        assert_eq!(picker.matches("").len(), 3);
+    }
+
+    #[test]
+    fn walk_skips_hidden_files_and_stops_at_the_depth_limit() {
+        let root =
+            env::temp_dir().join(format!("hecto-picker-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in &[".git", "a/b/c", ".hidden"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in &[
+            "top.txt",
+            ".env",
+            ".git/HEAD",
+            ".hidden/x.txt",
+            "a/one.txt",
+            "a/b/two.txt",
+            "a/b/c/three.txt",
+        ] {
+            fs::write(root.join(file), "").unwrap();
+        }
+
+        let files = |depth| {
+            let mut files = files_under(&root, depth);
+            files.sort();
+            files
+        };
+        assert_eq!(files(0), vec![PathBuf::from("top.txt")]);
+        assert_eq!(
+            files(2),
+            vec![
+                PathBuf::from("a/b/two.txt"),
+                PathBuf::from("a/one.txt"),
+                PathBuf::from("top.txt"),
+            ]
+        );
+        assert_eq!(files(9).len(), 4);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
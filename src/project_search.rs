@@ -0,0 +1,83 @@
+use crate::{picker, Row, SearchDirection};
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Files that start with more than this many bytes without a newline, or
+/// with a NUL byte, are taken to be binary and skipped.
+const BINARY_SNIFF_LEN: usize = 8192;
+
+/// Finds `query` in the files under `root`, at most `max_depth` directories
+/// deep, skipping the same files as the file picker as well as binary
+/// files. Gives the path of each file relative to `root`, and the number,
+/// counted from 0, and text of each line containing a match. Files that
+/// can't be read as UTF-8 are skipped.
+pub fn search_files(
+    root: &Path,
+    query: &str,
+    max_depth: usize,
+) -> Vec<(PathBuf, usize, String)> {
+    let mut hits = Vec::new();
+    if query.is_empty() {
+        return hits;
+    }
+
+    let mut files = picker::files_under(root, max_depth);
+    files.sort();
+    for file in files {
+        let contents = match fs::read(root.join(&file)) {
+            Ok(contents) if !is_binary(&contents) => contents,
+            _ => continue,
+        };
+        let contents = match String::from_utf8(contents) {
+            Ok(contents) => contents,
+            Err(_) => continue,
+        };
+        let contents = contents.strip_prefix('\u{feff}').unwrap_or(&contents);
+        for (line, text) in contents.lines().enumerate() {
+            if Row::from(text)
+                .find(query, 0, SearchDirection::Forward)
+                .is_some()
+            {
+                hits.push((file.clone(), line, text.to_string()));
+            }
+        }
+    }
+    hits
+}
+
+fn is_binary(contents: &[u8]) -> bool {
+    let start = contents.get(..BINARY_SNIFF_LEN).unwrap_or(contents);
+    start.contains(&0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, process};
+
+    #[test]
+    fn finds_lines_in_utf8_text_files_only() {
+        let root =
+            env::temp_dir().join(format!("hecto-search-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.txt"), "\u{feff}needle first\nhay\nneedle")
+            .unwrap();
+        fs::write(root.join("b.bin"), b"needle\0").unwrap();
+        fs::write(root.join("c.txt"), b"needle \xff").unwrap();
+
+        assert_eq!(
+            search_files(&root, "needle", 1),
+            vec![
+                (PathBuf::from("a.txt"), 0, String::from("needle first")),
+                (PathBuf::from("a.txt"), 2, String::from("needle")),
+            ]
+        );
+        assert!(search_files(&root, "", 1).is_empty());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// How many files a `RecentFiles` remembers by default.
+const DEFAULT_CAPACITY: usize = 50;
+
+/// The files opened or saved most recently, newest first, kept in a state
+/// file with one path per line so that they are remembered across sessions.
+pub struct RecentFiles {
+    state_file: PathBuf,
+    files: Vec<PathBuf>,
+    capacity: usize,
+}
+
+impl RecentFiles {
+    /// Reads the list from `state_file`, skipping files that no longer
+    /// exist. A missing or unreadable state file gives an empty list.
+    pub fn load(state_file: PathBuf) -> Self {
+        let files = fs::read_to_string(&state_file)
+            .unwrap_or_default()
+            .lines()
+            .map(PathBuf::from)
+            .filter(|file| file.exists())
+            .take(DEFAULT_CAPACITY)
+            .collect();
+        Self {
+            state_file,
+            files,
+            capacity: DEFAULT_CAPACITY,
+        }
+    }
+
+    /// `$XDG_STATE_HOME/hecto/recent`, or `~/.local/state/hecto/recent`.
+    pub fn default_state_file() -> Option<PathBuf> {
+        let state_dir = env::var_os("XDG_STATE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                env::var_os("HOME")
+                    .map(|home| PathBuf::from(home).join(".local/state"))
+            })?;
+        Some(state_dir.join("hecto").join("recent"))
+    }
+
+    /// Moves `file` to the front of the list, dropping the oldest entry if
+    /// the list is full, and writes the list out.
+    pub fn push(&mut self, file: &Path) -> Result<(), io::Error> {
+        let file = file.canonicalize()?;
+        self.files.retain(|recent| *recent != file);
+        self.files.insert(0, file);
+        self.files.truncate(self.capacity);
+        self.save()
+    }
+
+    /// The remembered files, newest first.
+    pub fn recent_files(&self) -> Vec<PathBuf> {
+        self.files.clone()
+    }
+
+    fn save(&self) -> Result<(), io::Error> {
+        if let Some(dir) = self.state_file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut contents = String::new();
+        for file in &self.files {
+            if let Some(file) = file.to_str() {
+                contents.push_str(file);
+                contents.push('\n');
+            }
+        }
+        fs::write(&self.state_file, contents)
+    }
+}
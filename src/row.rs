@@ -1,9 +1,21 @@
-use crate::{highlighting, Highlighter, HighlightingOptions, SearchDirection};
+use crate::{
+    highlighting, Diagnostic, Highlighter, HighlightingOptions, SearchDirection,
+};
 
 use termion::color;
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
 
-use std::cmp;
+use std::{
+    cmp,
+    fmt::{self, Write},
+    iter,
+    ops::Range,
+};
+
+pub(crate) const TAB_WIDTH: usize = 2;
+const WHITESPACE_COLOR: color::Rgb = color::Rgb(88, 110, 117);
+pub(crate) const RULER_COLOR: color::Rgb = color::Rgb(48, 48, 48);
 
 #[derive(Default)]
 pub struct Row {
@@ -11,6 +23,11 @@ pub struct Row {
     highlighting: Vec<highlighting::Type>,
     len: usize,
     pub is_highlighted: bool,
+    /// Whether the row was highlighted as starting, and found to end,
+    /// inside a multiline comment. A highlighted row is only redone if it
+    /// now starts differently.
+    starts_in_comment: bool,
+    ends_in_comment: bool,
 }
 
 impl From<&str> for Row {
@@ -20,47 +37,290 @@ impl From<&str> for Row {
             highlighting: Vec::new(),
             len: slice.graphemes(true).count(),
             is_highlighted: false,
+            starts_in_comment: false,
+            ends_in_comment: false,
         }
     }
 }
 
+/// Writes the row's text as is, without highlighting or line ending.
+impl fmt::Display for Row {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.string)
+    }
+}
+
 impl Row {
-    pub fn render(&self, start: usize, end: usize) -> String {
-        let end = cmp::min(end, self.string.len());
-        let start = cmp::min(start, end);
+    /// Renders the graphemes that fall between the display columns `start`
+    /// and `end`, filling exactly the columns in between that the row
+    /// reaches.
+    ///
+    /// With `hyperlinks` set, URLs are wrapped in OSC 8 escape sequences so
+    /// that terminals supporting them make them clickable. Other terminals
+    /// ignore the sequences.
+    ///
+    /// With `show_whitespace` set, spaces are drawn as `·` and tabs as `→`,
+    /// dimmed, in the columns they would otherwise leave blank.
+    ///
+    /// With a `ruler` column, whatever covers that display column, a tab or
+    /// a wide grapheme included, gets a shaded background. Rows that don't
+    /// reach it are left to the caller to pad.
+    ///
+    /// The row is drawn on whatever background the caller set before it.
+    /// Given that `background`, the ruler's shading goes back to it rather
+    /// than to the terminal's own.
+    pub fn render(
+        &self,
+        start: usize,
+        end: usize,
+        hyperlinks: bool,
+        show_whitespace: bool,
+        ruler: Option<usize>,
+        background: Option<color::Rgb>,
+    ) -> String {
         let mut result = String::new();
-        let mut current_highlighting = &highlighting::Type::None;
+        let base_bg = bg_string(background);
+        let mut current_highlighting = highlighting::Type::None;
+        let mut column: usize = 0;
+        let links = if hyperlinks {
+            find_urls(&self.string)
+        } else {
+            vec![]
+        };
+        let mut current_link: Option<&str> = None;
 
-        #[allow(clippy::integer_arithmetic)]
-        for (index, grapheme) in self.string[..]
-            .graphemes(true)
-            .enumerate()
-            .skip(start)
-            .take(end - start)
+        for ((byte_index, grapheme), highlighting_type) in self.string[..]
+            .grapheme_indices(true)
+            .zip(self.grapheme_highlighting())
         {
-            if let Some(c) = grapheme.chars().next() {
-                let highlighting_type = self
-                    .highlighting
-                    .get(index)
-                    .unwrap_or(&highlighting::Type::None);
+            if column >= end {
+                break;
+            }
+            let width = grapheme_width(grapheme);
+            let next_column = column.saturating_add(width);
 
+            if next_column > start {
+                let link = links
+                    .iter()
+                    .find(|link| link.contains(&byte_index))
+                    .and_then(|link| self.string.get(link.clone()));
+                if link != current_link {
+                    current_link = link;
+                    result.push_str(&hyperlink(link.unwrap_or_default()));
+                }
+                let on_ruler = ruler.map_or(false, |ruler| {
+                    column <= ruler && ruler < next_column
+                });
+                if on_ruler {
+                    let _ = write!(result, "{}", color::Bg(RULER_COLOR));
+                }
+                let visible_space = show_whitespace
+                    && (grapheme == " " || grapheme == "\t")
+                    && column >= start
+                    && next_column <= end;
+                if visible_space {
+                    // The glyph is followed by the plain color, for the next
+                    // grapheme to change from if it needs another.
+                    current_highlighting = highlighting::Type::None;
+                    let glyph = if grapheme == " " { "·" } else { "→" };
+                    let _ = write!(
+                        result,
+                        "{}{}{}{}",
+                        color::Fg(WHITESPACE_COLOR),
+                        glyph,
+                        " ".repeat(width.saturating_sub(1)),
+                        highlighting::Type::None.fg_string()
+                    );
+                    if on_ruler {
+                        result.push_str(&base_bg);
+                    }
+                    column = next_column;
+                    continue;
+                }
                 if highlighting_type != current_highlighting {
                     current_highlighting = highlighting_type;
                     result.push_str(highlighting_type.fg_string().as_str());
                 }
 
-                if c == '\t' {
-                    result.push_str("  ");
+                // Tabs, and wide graphemes cut by an edge of the window,
+                // become spaces over their visible columns so that the rest
+                // of the row stays in its columns.
+                if grapheme == "\t" || column < start || next_column > end {
+                    let visible = cmp::min(next_column, end)
+                        .saturating_sub(cmp::max(column, start));
+                    result.push_str(&" ".repeat(visible));
+                } else if grapheme.chars().any(|c| control_picture(c).is_some())
+                {
+                    result.extend(
+                        grapheme
+                            .chars()
+                            .map(|c| control_picture(c).unwrap_or(c)),
+                    );
                 } else {
-                    result.push(c);
+                    result.push_str(grapheme);
+                }
+                if on_ruler {
+                    result.push_str(&base_bg);
                 }
             }
+            column = next_column;
         }
 
+        if current_link.is_some() {
+            result.push_str(&hyperlink(""));
+        }
         result.push_str(termion::color::Fg(color::Reset).to_string().as_str());
         result
     }
 
+    /// Whether the row ends inside a multiline comment, as of when it was
+    /// last highlighted.
+    pub(crate) const fn ends_in_comment(&self) -> bool {
+        self.ends_in_comment
+    }
+
+    /// Contiguous runs of graphemes sharing a highlighting type, as
+    /// `(start, end, type)` grapheme ranges with `end` exclusive.
+    #[allow(clippy::arithmetic_side_effects)]
+    pub fn highlight_spans(&self) -> Vec<(usize, usize, highlighting::Type)> {
+        let mut spans: Vec<(usize, usize, highlighting::Type)> = Vec::new();
+
+        for (index, hl_type) in self.grapheme_highlighting().enumerate() {
+            match spans.last_mut() {
+                Some((_, end, last)) if *last == hl_type => *end = index + 1,
+                _ => spans.push((index, index + 1, hl_type)),
+            }
+        }
+        spans
+    }
+
+    /// The first char of the grapheme at `index`.
+    pub(crate) fn char_at(&self, index: usize) -> Option<char> {
+        self.string[..].graphemes(true).nth(index)?.chars().next()
+    }
+
+    /// The word containing the grapheme at `index`, if it isn't a separator.
+    pub(crate) fn word_at(&self, index: usize) -> Option<&str> {
+        let (at, _) = self.string[..].grapheme_indices(true).nth(index)?;
+        let start = self.string[..at]
+            .rfind(is_separator)
+            .map_or(0, |start| start.saturating_add(1));
+        let end = self.string[at..]
+            .find(is_separator)
+            .map_or(self.string.len(), |end| at.saturating_add(end));
+        self.string.get(start..end).filter(|word| !word.is_empty())
+    }
+
+    /// Index of the first grapheme that isn't whitespace, or 0 if the row is
+    /// blank.
+    pub fn first_non_blank(&self) -> usize {
+        self.string[..]
+            .graphemes(true)
+            .position(|grapheme| !grapheme.chars().all(char::is_whitespace))
+            .unwrap_or(0)
+    }
+
+    /// Index of the start of the word before `index`, skipping whitespace in
+    /// between. A run of punctuation counts as a word of its own.
+    pub(crate) fn previous_word_start(&self, index: usize) -> usize {
+        let kinds: Vec<u8> = self.string[..]
+            .graphemes(true)
+            .take(index)
+            .map(|grapheme| match grapheme.chars().next() {
+                Some(c) if c.is_whitespace() => 0,
+                Some(c) if is_separator(c) => 1,
+                _ => 2,
+            })
+            .collect();
+
+        let mut start = kinds.len();
+        while start > 0 && kinds.get(start.saturating_sub(1)) == Some(&0) {
+            start = start.saturating_sub(1);
+        }
+        let kind = start.checked_sub(1).and_then(|prev| kinds.get(prev));
+        while start > 0 && kinds.get(start.saturating_sub(1)) == kind {
+            start = start.saturating_sub(1);
+        }
+        start
+    }
+
+    /// The highlighting type of the grapheme at `index`, or `None` if the row
+    /// hasn't been highlighted yet or `index` is past its end.
+    pub fn highlighting_at(&self, index: usize) -> Option<highlighting::Type> {
+        if !self.is_highlighted {
+            return None;
+        }
+        self.grapheme_highlighting().nth(index)
+    }
+
+    /// Index of the first char of the grapheme at `index`, counting from the
+    /// start of the row.
+    fn char_offset(&self, index: usize) -> usize {
+        self.string[..]
+            .graphemes(true)
+            .take(index)
+            .map(|grapheme| grapheme.chars().count())
+            .sum()
+    }
+
+    /// The highlighting type of each grapheme. Highlighting is computed per
+    /// char, so a grapheme takes the type of its first char.
+    fn grapheme_highlighting(
+        &self,
+    ) -> impl Iterator<Item = highlighting::Type> + '_ {
+        let mut char_index: usize = 0;
+        self.string[..].graphemes(true).map(move |grapheme| {
+            let hl_type = self
+                .highlighting
+                .get(char_index)
+                .copied()
+                .unwrap_or(highlighting::Type::None);
+            char_index = char_index.saturating_add(grapheme.chars().count());
+            hl_type
+        })
+    }
+
+    /// Number of terminal columns the row occupies when rendered.
+    pub fn display_width(&self) -> usize {
+        self.string[..].graphemes(true).map(grapheme_width).sum()
+    }
+
+    /// Display column at which the grapheme at `index` starts. Indexes past
+    /// the end map to the column just after the last grapheme.
+    pub fn column_of(&self, index: usize) -> usize {
+        self.string[..]
+            .graphemes(true)
+            .take(index)
+            .map(grapheme_width)
+            .sum()
+    }
+
+    /// Index of the grapheme covering the display column `column`, or `len()`
+    /// if the column lies past the end of the row.
+    pub fn index_at_column(&self, column: usize) -> usize {
+        let mut current: usize = 0;
+        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
+            current = current.saturating_add(grapheme_width(grapheme));
+            if current > column {
+                return index;
+            }
+        }
+        self.len
+    }
+
+    /// Index of the grapheme containing the char at `char_index`, or
+    /// `len()` if the row has fewer chars.
+    pub fn index_of_char(&self, char_index: usize) -> usize {
+        let mut current: usize = 0;
+        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
+            current = current.saturating_add(grapheme.chars().count());
+            if current > char_index {
+                return index;
+            }
+        }
+        self.len
+    }
+
     #[inline]
     pub const fn len(&self) -> usize {
         self.len
@@ -71,24 +331,23 @@ impl Row {
         self.len == 0
     }
 
+    /// Number of whitespace graphemes at the start of the row.
+    pub fn indentation(&self) -> usize {
+        self.leading_whitespace().graphemes(true).count()
+    }
+
+    /// The whitespace at the start of the row.
+    pub fn leading_whitespace(&self) -> &str {
+        let text = self.string.trim_start_matches(char::is_whitespace);
+        &self.string[..self.string.len().saturating_sub(text.len())]
+    }
+
+    /// Inserts `c` before the grapheme at `at`. A combining char merges into
+    /// the grapheme before it, in which case `len` stays the same.
     pub(crate) fn insert(&mut self, at: usize, c: char) {
-        if at >= self.len() {
-            self.string.push(c);
-            self.len += 1;
-            return;
-        }
-        let mut result = String::new();
-        let mut length = 0;
-        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
-            length += 1;
-            if index == at {
-                length += 1;
-                result.push(c);
-            }
-            result.push_str(grapheme);
-        }
-        self.len = length;
-        self.string = result;
+        let byte_index = self.byte_index(at);
+        self.string.insert(byte_index, c);
+        self.update_len();
     }
 
     pub(crate) fn delete(&mut self, at: usize) {
@@ -96,48 +355,66 @@ impl Row {
             return;
         }
 
-        let mut result = String::new();
-        let mut length = 0;
-        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
-            if index != at {
-                length += 1;
-                result.push_str(grapheme);
-            }
-        }
-        self.len = length;
-        self.string = result;
+        let start = self.byte_index(at);
+        let end = self.byte_index(at.saturating_add(1));
+        self.string.replace_range(start..end, "");
+        self.update_len();
     }
 
     pub(crate) fn append(&mut self, new: &Self) {
-        self.string = format!("{}{}", self.string, new.string);
-        self.len += new.len;
+        self.string.push_str(&new.string);
+        self.update_len();
     }
 
-    pub(crate) fn split(&mut self, at: usize) -> Self {
-        let mut row = String::new();
-        let mut length = 0;
-        let mut splitted_row = String::new();
-        let mut splitted_length = 0;
-        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
-            if index < at {
-                length += 1;
-                row.push_str(grapheme);
-            } else {
-                splitted_length += 1;
-                splitted_row.push_str(grapheme);
-            }
-        }
+    /// The graphemes from `start` up to, but not including, `end`.
+    pub(crate) fn slice(&self, start: usize, end: usize) -> &str {
+        let start = self.byte_index(start);
+        let end = cmp::max(start, self.byte_index(end));
+        #[allow(clippy::indexing_slicing)]
+        &self.string[start..end]
+    }
+
+    /// Byte offset of the grapheme at `index`, or the length of the string
+    /// for indexes past the end.
+    fn byte_index(&self, index: usize) -> usize {
+        self.string[..]
+            .grapheme_indices(true)
+            .nth(index)
+            .map_or(self.string.len(), |(byte_index, _)| byte_index)
+    }
 
-        self.string = row;
-        self.len = length;
+    /// Recounts the graphemes after an edit, which may have merged or split
+    /// clusters around it, and marks the row for highlighting again.
+    fn update_len(&mut self) {
+        self.len = self.string[..].graphemes(true).count();
         self.is_highlighted = false;
+    }
 
-        Self {
-            string: splitted_row,
-            highlighting: Vec::new(),
-            len: splitted_length,
-            is_highlighted: false,
-        }
+    /// Splits the row before the grapheme at `at`, leaving this row with
+    /// the graphemes before it and returning the rest.
+    pub(crate) fn split(&mut self, at: usize) -> Self {
+        let (head, tail) = self.split_at(at);
+        *self = head;
+        tail
+    }
+
+    /// The graphemes before `at` and those from `at` on, as two new rows.
+    /// Unlike [`Row::split`], this row is left as it is.
+    #[allow(clippy::indexing_slicing)]
+    pub fn split_at(&self, at: usize) -> (Self, Self) {
+        let byte_index = self.byte_index(at);
+        (
+            Self::from(&self.string[..byte_index]),
+            Self::from(&self.string[byte_index..]),
+        )
+    }
+
+    /// Number of runs of non-separator characters.
+    pub fn word_count(&self) -> usize {
+        self.string
+            .split(is_separator)
+            .filter(|word| !word.is_empty())
+            .count()
     }
 
     pub(crate) fn as_bytes(&self) -> &[u8] {
@@ -159,51 +436,57 @@ impl Row {
             SearchDirection::Backward => (0, at),
         };
 
-        #[allow(clippy::integer_arithmetic)]
+        #[allow(clippy::arithmetic_side_effects)]
         let substring = self.string[..]
             .graphemes(true)
             .skip(start)
             .take(end - start)
             .collect::<String>();
 
-        let matching_byte_index = match direction {
-            SearchDirection::Forward => substring.find(query),
-            SearchDirection::Backward => substring.rfind(query),
-        };
+        // A match only counts if it starts and ends on grapheme boundaries,
+        // so that e.g. "e" doesn't match the first half of "e\u{301}".
+        let boundaries: Vec<usize> = substring[..]
+            .grapheme_indices(true)
+            .map(|(byte_index, _)| byte_index)
+            .chain(iter::once(substring.len()))
+            .collect();
+        let mut matches = boundaries
+            .iter()
+            .enumerate()
+            .filter(|(_, &byte_index)| {
+                substring[byte_index..].starts_with(query)
+                    && boundaries
+                        .binary_search(&byte_index.saturating_add(query.len()))
+                        .is_ok()
+            })
+            .map(|(grapheme_index, _)| start.saturating_add(grapheme_index));
 
-        if let Some(matching_byte_index) = matching_byte_index {
-            for (grapheme_index, (byte_index, _)) in
-                substring[..].grapheme_indices(true).enumerate()
-            {
-                if matching_byte_index == byte_index {
-                    #[allow(clippy::integer_arithmetic)]
-                    return Some(start + grapheme_index);
-                }
-            }
+        match direction {
+            SearchDirection::Forward => matches.next(),
+            SearchDirection::Backward => matches.next_back(),
         }
-        None
     }
 }
 
 impl Highlighter for Row {
-    #[allow(clippy::indexing_slicing, clippy::integer_arithmetic)]
+    #[allow(clippy::indexing_slicing, clippy::arithmetic_side_effects)]
     fn highlight(
         &mut self,
         opts: &HighlightingOptions,
         word: Option<&str>,
+        diagnostics: &[Diagnostic],
         start_with_comment: bool,
     ) -> bool {
         let chars = self.string.chars().collect::<Vec<char>>();
-        if self.is_highlighted && word.is_none() {
-            if let Some(hl_type) = self.highlighting.last() {
-                if *hl_type == highlighting::Type::MultilineComment
-                    && self.string.len() > 1
-                    && self.string[self.string.len() - 2..] == *"*/"
-                {
-                    return true;
-                }
-            }
-            return false;
+        let ml_close = opts.multiline_comment().map(|(_, close)| close);
+        let at_line_start = opts.multiline_comment_at_line_start();
+        let ends_open = |row: &Self| {
+            at_line_start
+                || ml_close.map_or(false, |close| !row.string.ends_with(close))
+        };
+
+        if self.is_highlighted && self.starts_in_comment == start_with_comment {
+            return self.ends_in_comment;
         }
 
         self.highlighting = vec![];
@@ -211,13 +494,33 @@ impl Highlighter for Row {
         let mut index = 0;
         let mut in_ml_comment = start_with_comment;
 
-        if in_ml_comment {
-            let closing_index =
-                if let Some(closing_index) = self.string.find("*/") {
-                    closing_index + 2
+        if at_line_start {
+            // The delimiters comment out the whole of their lines, and the
+            // lines in between.
+            if let Some((open, close)) = opts.multiline_comment() {
+                let continues = if start_with_comment {
+                    Some(!self.string.starts_with(close))
+                } else if self.string.starts_with(open) {
+                    Some(true)
                 } else {
-                    chars.len()
+                    None
                 };
+                if let Some(continues) = continues {
+                    for _ in 0..chars.len() {
+                        self.highlighting
+                            .push(highlighting::Type::MultilineComment);
+                    }
+                    index = chars.len();
+                    in_ml_comment = continues;
+                }
+            }
+        } else if in_ml_comment {
+            let closing_index = ml_close
+                .and_then(|close| {
+                    find_at(&chars, 0, close)
+                        .map(|start| start + close.chars().count())
+                })
+                .unwrap_or(chars.len());
 
             for _ in 0..closing_index {
                 self.highlighting.push(highlighting::Type::MultilineComment);
@@ -225,19 +528,32 @@ impl Highlighter for Row {
             index = closing_index;
         }
 
+        if opts.key_values() {
+            self.highlight_key_value(opts, &chars);
+            index = chars.len();
+        } else if index == 0 && opts.targets() {
+            index = self.highlight_target(&chars);
+        }
+
         while let Some(c) = chars.get(index) {
-            if self.highlight_multiline_comment(&mut index, &opts, *c, &chars) {
+            if self.highlight_multiline_comment(&mut index, opts, *c, &chars) {
                 in_ml_comment = true;
                 continue;
             }
             in_ml_comment = false;
 
-            if self.highlight_char(&mut index, opts, *c, &chars)
+            if self.highlight_tag(&mut index, opts, *c, &chars)
+                || self.highlight_entity(&mut index, opts, *c, &chars)
+                || self.highlight_char(&mut index, opts, *c, &chars)
+                || self.highlight_variable(&mut index, opts, *c, &chars)
+                || self.highlight_symbol(&mut index, opts, *c, &chars)
                 || self.highlight_comment(&mut index, opts, *c, &chars)
-                || self.highlight_primary_keywords(&mut index, &opts, &chars)
-                || self.highlight_secondary_keywords(&mut index, &opts, &chars)
+                || self.highlight_long_string(&mut index, opts, *c, &chars)
+                || self.highlight_primary_keywords(&mut index, opts, &chars)
+                || self.highlight_secondary_keywords(&mut index, opts, &chars)
                 || self.highlight_string(&mut index, opts, *c, &chars)
                 || self.highlight_number(&mut index, opts, *c, &chars)
+                || self.highlight_operators(&mut index, opts, &chars)
             {
                 continue;
             }
@@ -246,38 +562,47 @@ impl Highlighter for Row {
         }
 
         self.highlight_match(word);
-
-        if in_ml_comment
-            && &self.string[self.string.len().saturating_sub(2)..] != "*/"
-        {
-            return true;
-        }
+        self.highlight_diagnostics(diagnostics);
 
         self.is_highlighted = true;
-        false
+        self.starts_in_comment = start_with_comment;
+        self.ends_in_comment = in_ml_comment && ends_open(self);
+        self.ends_in_comment
     }
 
     fn highlight_match(&mut self, word: Option<&str>) {
-        if let Some(word) = word {
-            if word.is_empty() {
-                return;
-            }
+        let word = match word {
+            Some(word) if !word.is_empty() => word,
+            _ => return,
+        };
+        let word_len = word[..].graphemes(true).count();
+        let word_chars = word.chars().count();
 
-            let mut index = 0;
-            while let Some(search_match) =
-                self.find(word, index, SearchDirection::Forward)
+        let mut index = 0;
+        while let Some(search_match) =
+            self.find(word, index, SearchDirection::Forward)
+        {
+            let start = self.char_offset(search_match);
+            for hl_type in
+                self.highlighting.iter_mut().skip(start).take(word_chars)
             {
-                if let Some(next_index) =
-                    search_match.checked_add(word[..].graphemes(true).count())
-                {
-                    #[allow(clippy::indexing_slicing)]
-                    for i in index.saturating_add(search_match)..next_index {
-                        self.highlighting[i] = highlighting::Type::Match;
-                    }
-                    index = next_index;
-                } else {
-                    break;
-                }
+                *hl_type = highlighting::Type::Match;
+            }
+            index = search_match.saturating_add(word_len);
+        }
+    }
+
+    /// Marks the columns of `diagnostics` on top of the other highlighting.
+    /// Where diagnostics overlap, the most severe one shows.
+    fn highlight_diagnostics(&mut self, diagnostics: &[Diagnostic]) {
+        let mut diagnostics: Vec<&Diagnostic> = diagnostics.iter().collect();
+        diagnostics.sort_by_key(|diagnostic| diagnostic.severity);
+
+        for diagnostic in diagnostics {
+            let start = self.char_offset(diagnostic.col_start);
+            let end = self.char_offset(diagnostic.col_end);
+            for hl_type in self.highlighting.iter_mut().take(end).skip(start) {
+                *hl_type = diagnostic.severity.highlighting_type();
             }
         }
     }
@@ -290,27 +615,94 @@ impl Highlighter for Row {
         chars: &[char],
     ) -> bool {
         if opts.characters() && c == '\'' {
-            if let Some(next_char) = chars.get(index.saturating_add(1)) {
-                let closing_index = if *next_char == '\\' {
-                    index.saturating_add(3)
-                } else {
-                    index.saturating_add(2)
-                };
-                if let Some(closing_char) = chars.get(closing_index) {
-                    if *closing_char == '\'' {
-                        for _ in 0..=closing_index.saturating_sub(*index) {
-                            self.highlighting
-                                .push(highlighting::Type::Character);
-                            *index += 1;
-                        }
-                        return true;
-                    }
+            let start = index.saturating_add(1);
+            let closing_index = if chars.get(start) == Some(&'\\') {
+                start.saturating_add(escape_len(chars, start))
+            } else {
+                start.saturating_add(1)
+            };
+            if chars.get(closing_index) == Some(&'\'') {
+                for _ in 0..=closing_index.saturating_sub(*index) {
+                    self.highlighting.push(highlighting::Type::Character);
+                    *index += 1;
                 }
+                return true;
             }
         }
         false
     }
 
+    /// Highlights a whole line of a key-value file: a comment, a `[section]`
+    /// header, or the key of a `key = value` or `key: value` pair.
+    fn highlight_key_value(
+        &mut self,
+        opts: &HighlightingOptions,
+        chars: &[char],
+    ) {
+        let indent = chars.iter().take_while(|c| c.is_whitespace()).count();
+        let is_comment = opts
+            .comment_leaders()
+            .any(|leader| matches_at(chars, indent, leader));
+
+        let (hl_type, end) = if is_comment {
+            (highlighting::Type::Comment, chars.len())
+        } else if chars.get(indent) == Some(&'[') {
+            let end = find_at(chars, indent, "]")
+                .map_or(chars.len(), |close| close.saturating_add(1));
+            (highlighting::Type::Tag, end)
+        } else {
+            let separator = chars
+                .iter()
+                .position(|&c| c == '=' || c == ':')
+                .unwrap_or(indent);
+            let key_end = chars[..separator]
+                .iter()
+                .rposition(|c| !c.is_whitespace())
+                .map_or(indent, |last| last.saturating_add(1));
+            (highlighting::Type::Attribute, key_end)
+        };
+
+        for index in 0..chars.len() {
+            self.highlighting.push(if index >= indent && index < end {
+                hl_type
+            } else {
+                highlighting::Type::None
+            });
+        }
+    }
+
+    /// Highlights the targets of a rule line, such as `all build:` in
+    /// `all build: deps`, as tags. Assignments like `CC := gcc` and
+    /// indented recipe lines aren't rules. Returns where the targets end, or
+    /// 0 if the line isn't a rule.
+    fn highlight_target(&mut self, chars: &[char]) -> usize {
+        if chars
+            .first()
+            .map_or(true, |&c| c.is_whitespace() || c == '#')
+        {
+            return 0;
+        }
+        let colon = match chars.iter().position(|&c| c == ':' || c == '=') {
+            Some(colon) if chars.get(colon) == Some(&':') => colon,
+            _ => return 0,
+        };
+        let is_assignment =
+            matches_at(chars, colon, ":=") || matches_at(chars, colon, "::=");
+        if is_assignment {
+            return 0;
+        }
+
+        #[allow(clippy::indexing_slicing)]
+        let end = chars[..colon]
+            .iter()
+            .rposition(|c| !c.is_whitespace())
+            .map_or(0, |last| last.saturating_add(1));
+        for _ in 0..end {
+            self.highlighting.push(highlighting::Type::Tag);
+        }
+        end
+    }
+
     fn highlight_comment(
         &mut self,
         index: &mut usize,
@@ -318,15 +710,13 @@ impl Highlighter for Row {
         c: char,
         chars: &[char],
     ) -> bool {
-        if opts.comments() && c == '/' && *index < chars.len() {
-            if let Some(next_char) = chars.get(index.saturating_add(1)) {
-                if *next_char == '/' {
-                    for _ in *index..chars.len() {
-                        self.highlighting.push(highlighting::Type::Comment);
-                        *index += 1;
-                    }
-                    return true;
+        for leader in opts.comment_leaders() {
+            if leader.starts_with(c) && matches_at(chars, *index, leader) {
+                for _ in *index..chars.len() {
+                    self.highlighting.push(highlighting::Type::Comment);
+                    *index += 1;
                 }
+                return true;
             }
         }
         false
@@ -339,57 +729,277 @@ impl Highlighter for Row {
         c: char,
         chars: &[char],
     ) -> bool {
-        if opts.comments() && c == '/' && *index < chars.len() {
-            if let Some(next_char) = chars.get(index.saturating_add(1)) {
-                if *next_char == '*' {
-                    let closing_index = if let Some(closing_index) =
-                        self.string[*index + 2..].find("*/")
-                    {
-                        *index + closing_index + 4
-                    } else {
-                        chars.len()
-                    };
+        if opts.multiline_comment_at_line_start() {
+            return false;
+        }
+        if let Some((open, close)) = opts.multiline_comment() {
+            if open.starts_with(c) && matches_at(chars, *index, open) {
+                let body = index.saturating_add(open.chars().count());
+                let closing_index = find_at(chars, body, close)
+                    .map_or(chars.len(), |start| {
+                        start.saturating_add(close.chars().count())
+                    });
 
-                    for _ in *index..closing_index {
-                        self.highlighting
-                            .push(highlighting::Type::MultilineComment);
-                        *index += 1;
-                    }
-                    return true;
+                for _ in *index..closing_index {
+                    self.highlighting
+                        .push(highlighting::Type::MultilineComment);
+                    *index += 1;
                 }
-            };
+                return true;
+            }
         }
 
         false
     }
 
-    fn highlight_string(
+    fn highlight_tag(
         &mut self,
         index: &mut usize,
         opts: &HighlightingOptions,
         c: char,
         chars: &[char],
     ) -> bool {
-        if opts.strings() && c == '"' {
-            loop {
-                self.highlighting.push(highlighting::Type::String);
-                *index += 1;
+        if !opts.tags() || c != '<' {
+            return false;
+        }
+        let at = |offset: usize| chars.get(index.saturating_add(offset));
+        let prefix = match at(1) {
+            Some('/') | Some('!') | Some('?') => 2,
+            _ => 1,
+        };
+        if !at(prefix).map_or(false, |c| c.is_alphabetic()) {
+            return false;
+        }
 
-                if let Some(next_char) = chars.get(*index) {
-                    if *next_char == '"' {
-                        break;
-                    }
-                } else {
+        for _ in 0..prefix {
+            self.highlighting.push(highlighting::Type::Tag);
+            *index += 1;
+        }
+        while chars.get(*index).map_or(false, |c| is_name_char(*c)) {
+            self.highlighting.push(highlighting::Type::Tag);
+            *index += 1;
+        }
+
+        while let Some(next_char) = chars.get(*index) {
+            match next_char {
+                '>' => {
+                    self.highlighting.push(highlighting::Type::Tag);
+                    *index += 1;
                     break;
-                }
+                },
+                '/' | '?'
+                    if chars.get(index.saturating_add(1)) == Some(&'>') =>
+                {
+                    self.highlighting.push(highlighting::Type::Tag);
+                    self.highlighting.push(highlighting::Type::Tag);
+                    *index += 2;
+                    break;
+                },
+                '"' | '\'' => {
+                    let quote = *next_char;
+                    self.highlighting.push(highlighting::Type::String);
+                    *index += 1;
+                    while let Some(value_char) = chars.get(*index) {
+                        self.highlighting.push(highlighting::Type::String);
+                        *index += 1;
+                        if *value_char == quote {
+                            break;
+                        }
+                    }
+                },
+                c if is_name_char(*c) => {
+                    while chars.get(*index).map_or(false, |c| is_name_char(*c))
+                    {
+                        self.highlighting.push(highlighting::Type::Attribute);
+                        *index += 1;
+                    }
+                },
+                _ => {
+                    self.highlighting.push(highlighting::Type::None);
+                    *index += 1;
+                },
+            }
+        }
+        true
+    }
+
+    fn highlight_entity(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool {
+        if !opts.tags() || c != '&' {
+            return false;
+        }
+        let name_len = chars
+            .iter()
+            .skip(index.saturating_add(1))
+            .take_while(|c| c.is_ascii_alphanumeric() || **c == '#')
+            .count();
+        let semicolon = index.saturating_add(name_len).saturating_add(1);
+        if name_len == 0 || chars.get(semicolon) != Some(&';') {
+            return false;
+        }
+
+        for _ in *index..=semicolon {
+            self.highlighting.push(highlighting::Type::Entity);
+            *index += 1;
+        }
+        true
+    }
+
+    fn highlight_string(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool {
+        let escapes = if opts.string_delimiters().contains(&c) {
+            true
+        } else if opts.raw_string_delimiters().contains(&c) {
+            false
+        } else {
+            return false;
+        };
+
+        self.highlighting.push(highlighting::Type::String);
+        *index += 1;
+
+        while let Some(next_char) = chars.get(*index) {
+            if escapes
+                && (self.highlight_escape(index, chars)
+                    || self.highlight_variable(index, opts, *next_char, chars))
+            {
+                continue;
             }
             self.highlighting.push(highlighting::Type::String);
             *index += 1;
-            return true;
+            if *next_char == c {
+                break;
+            }
+        }
+        true
+    }
+
+    fn highlight_long_string(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool {
+        if let Some((open, close)) = opts.long_string() {
+            if open.starts_with(c) && matches_at(chars, *index, open) {
+                let body = index.saturating_add(open.chars().count());
+                let closing_index = find_at(chars, body, close)
+                    .map_or(chars.len(), |start| {
+                        start.saturating_add(close.chars().count())
+                    });
+
+                for _ in *index..closing_index {
+                    self.highlighting.push(highlighting::Type::String);
+                    *index += 1;
+                }
+                return true;
+            }
         }
         false
     }
 
+    #[allow(clippy::indexing_slicing, clippy::arithmetic_side_effects)]
+    fn highlight_variable(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool {
+        if !opts.variables() || c != '$' {
+            return false;
+        }
+
+        let rest = &chars[*index + 1..];
+        let len = match rest.first() {
+            Some('{') => rest
+                .iter()
+                .position(|c| *c == '}')
+                .map_or(rest.len(), |end| end + 1),
+            Some('(') if opts.make_variables() => rest
+                .iter()
+                .position(|c| *c == ')')
+                .map_or(rest.len(), |end| end + 1),
+            Some(next) if opts.make_variables() && "<^+%".contains(*next) => 1,
+            Some(next) if next.is_ascii_alphabetic() || *next == '_' => rest
+                .iter()
+                .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+                .count(),
+            Some(next)
+                if next.is_ascii_digit() || "@*#?$!-".contains(*next) =>
+            {
+                1
+            },
+            _ => return false,
+        };
+
+        for _ in 0..=len {
+            self.highlighting.push(highlighting::Type::Variable);
+            *index += 1;
+        }
+        true
+    }
+
+    fn highlight_symbol(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool {
+        if !opts.symbols() || c != ':' {
+            return false;
+        }
+        // Not the second colon of `::`, nor one after a name, as in a
+        // `key: value` hash entry or `a ?b:c`.
+        let previous = index.checked_sub(1).and_then(|i| chars.get(i));
+        let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+        if previous.map_or(false, |&p| p == ':' || is_word_char(p)) {
+            return false;
+        }
+        let start = index.saturating_add(1);
+        match chars.get(start) {
+            Some(&first) if first.is_alphabetic() || first == '_' => (),
+            _ => return false,
+        }
+        let mut end = start;
+        while chars.get(end).map_or(false, |&c| is_word_char(c)) {
+            end = end.saturating_add(1);
+        }
+        if let Some('?') | Some('!') = chars.get(end) {
+            end = end.saturating_add(1);
+        }
+
+        for _ in *index..end {
+            self.highlighting.push(highlighting::Type::Symbol);
+            *index += 1;
+        }
+        true
+    }
+
+    fn highlight_escape(&mut self, index: &mut usize, chars: &[char]) -> bool {
+        if chars.get(*index) != Some(&'\\') {
+            return false;
+        }
+
+        for _ in 0..escape_len(chars, *index) {
+            self.highlighting.push(highlighting::Type::Escape);
+            *index += 1;
+        }
+        true
+    }
+
     fn highlight_number(
         &mut self,
         index: &mut usize,
@@ -399,24 +1009,19 @@ impl Highlighter for Row {
     ) -> bool {
         if opts.numbers() && c.is_ascii_digit() {
             if *index > 0 {
-                #[allow(clippy::indexing_slicing, clippy::integer_arithmetic)]
+                #[allow(
+                    clippy::indexing_slicing,
+                    clippy::arithmetic_side_effects
+                )]
                 let prev_char = chars[*index - 1];
                 if !is_separator(prev_char) {
                     return false;
                 }
             }
 
-            loop {
+            for _ in 0..number_len(chars, *index) {
                 self.highlighting.push(highlighting::Type::Number);
                 *index += 1;
-
-                if let Some(next_char) = chars.get(*index) {
-                    if *next_char != '.' && !next_char.is_ascii_digit() {
-                        break;
-                    }
-                } else {
-                    break;
-                }
             }
             return true;
         }
@@ -446,7 +1051,7 @@ impl Highlighter for Row {
             }
         }
 
-        for _ in 0..substring.len() {
+        for _ in 0..substring.chars().count() {
             self.highlighting.push(hl_type);
             *index += 1;
         }
@@ -457,11 +1062,12 @@ impl Highlighter for Row {
         &mut self,
         index: &mut usize,
         chars: &[char],
-        keywords: &[&str],
+        keywords: &[String],
+        ignore_case: bool,
         hl_type: highlighting::Type,
     ) -> bool {
         if *index > 0 {
-            #[allow(clippy::indexing_slicing, clippy::integer_arithmetic)]
+            #[allow(clippy::indexing_slicing, clippy::arithmetic_side_effects)]
             let prev_char = chars[*index - 1];
             if !is_separator(prev_char) {
                 return false;
@@ -469,15 +1075,30 @@ impl Highlighter for Row {
         }
 
         for word in keywords {
-            if *index < chars.len().saturating_sub(word.len()) {
-                #[allow(clippy::indexing_slicing, clippy::integer_arithmetic)]
-                let next_char = chars[*index + word.len()];
-                if !is_separator(next_char) {
-                    continue;
-                }
+            // The keyword must be followed by a separator or the end of the
+            // row.
+            let len = word.chars().count();
+            let end = index.saturating_add(len);
+            if len == 0 || chars.get(end).map_or(false, |c| !is_separator(*c)) {
+                continue;
             }
 
-            if self.highlight_str(index, word, chars, hl_type) {
+            let candidate = match chars.get(*index..end) {
+                Some(candidate) => candidate,
+                None => continue,
+            };
+            let matched = candidate.iter().zip(word.chars()).all(|(&a, b)| {
+                if ignore_case {
+                    a.eq_ignore_ascii_case(&b)
+                } else {
+                    a == b
+                }
+            });
+            if matched {
+                for _ in 0..len {
+                    self.highlighting.push(hl_type);
+                    *index += 1;
+                }
                 return true;
             }
         }
@@ -490,10 +1111,14 @@ impl Highlighter for Row {
         opts: &HighlightingOptions,
         chars: &[char],
     ) -> bool {
+        if opts.keywords_at_line_start() && !starts_line(chars, *index) {
+            return false;
+        }
         self.highlight_keywords(
             index,
             chars,
             opts.secondary_keywords(),
+            opts.keywords_case_insensitive(),
             highlighting::Type::SecondaryKeyword,
         )
     }
@@ -504,15 +1129,525 @@ impl Highlighter for Row {
         opts: &HighlightingOptions,
         chars: &[char],
     ) -> bool {
+        if opts.keywords_at_line_start() && !starts_line(chars, *index) {
+            return false;
+        }
         self.highlight_keywords(
             index,
             chars,
             opts.primary_keywords(),
+            opts.keywords_case_insensitive(),
             highlighting::Type::PrimaryKeyword,
         )
     }
+
+    /// Highlights the longest operator starting at `index`, so that `=>`
+    /// isn't taken as `=` followed by `>`. Operators don't need separators
+    /// around them, since they abut names as in `a+b`.
+    fn highlight_operators(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        chars: &[char],
+    ) -> bool {
+        let len = opts
+            .operators()
+            .iter()
+            .filter(|operator| matches_at(chars, *index, operator))
+            .map(|operator| operator.chars().count())
+            .max()
+            .unwrap_or(0);
+        for _ in 0..len {
+            self.highlighting.push(highlighting::Type::Operator);
+            *index += 1;
+        }
+        len > 0
+    }
+}
+
+/// Whether only whitespace comes before `index`.
+fn starts_line(chars: &[char], index: usize) -> bool {
+    chars.iter().take(index).all(|c| c.is_whitespace())
+}
+
+/// Byte ranges of the `http://` and `https://` URLs in `s`. A URL runs up
+/// to the next whitespace, minus any punctuation that more likely ends the
+/// surrounding sentence.
+fn find_urls(s: &str) -> Vec<Range<usize>> {
+    let mut urls = Vec::new();
+    let mut from = 0;
+
+    while let Some(start) = s.get(from..).and_then(|rest| rest.find("http")) {
+        let start = from.saturating_add(start);
+        let rest = s.get(start..).unwrap_or_default();
+        let len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let url = rest.get(..len).unwrap_or_default();
+        let url = url.trim_end_matches(
+            &['.', ',', ';', ':', '!', '?', ')', '\'', '"'][..],
+        );
+
+        let is_url = (url.starts_with("http://")
+            || url.starts_with("https://"))
+            && url.len() > "https://".len();
+        let at_word_start = s
+            .get(..start)
+            .and_then(|before| before.chars().next_back())
+            .map_or(true, |c| !c.is_alphanumeric());
+        if is_url && at_word_start {
+            urls.push(start..start.saturating_add(url.len()));
+        }
+        from = start.saturating_add(len.max(1));
+    }
+    urls
+}
+
+/// The OSC 8 sequence starting a hyperlink to `url`, or ending the current
+/// one if `url` is empty.
+fn hyperlink(url: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\", url)
+}
+
+/// The sequence setting the background to `background`, or back to the
+/// terminal's own if there is none.
+pub(crate) fn bg_string(background: Option<color::Rgb>) -> String {
+    match background {
+        Some(background) => color::Bg(background).to_string(),
+        None => color::Bg(color::Reset).to_string(),
+    }
+}
+
+/// Index of the first occurrence of `s` in `chars` at or after `from`.
+fn find_at(chars: &[char], from: usize, s: &str) -> Option<usize> {
+    (from..chars.len()).find(|index| matches_at(chars, *index, s))
+}
+
+/// Whether `c` can be part of a markup tag or attribute name.
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '-' | '_' | ':' | '.')
+}
+
+/// Whether `chars` contains `s` starting at `index`.
+fn matches_at(chars: &[char], index: usize, s: &str) -> bool {
+    s.chars()
+        .enumerate()
+        .all(|(offset, c)| chars.get(index.saturating_add(offset)) == Some(&c))
+}
+
+/// Length in chars of the escape sequence starting with the backslash at
+/// `index`. Never reaches past the end of `chars`, so a trailing backslash is
+/// a sequence of one.
+#[allow(clippy::indexing_slicing)]
+fn escape_len(chars: &[char], index: usize) -> usize {
+    let len = match chars.get(index.saturating_add(1)) {
+        Some('u') if chars.get(index.saturating_add(2)) == Some(&'{') => chars
+            [index..]
+            .iter()
+            .position(|c| *c == '}')
+            .map_or(chars.len(), |end| end.saturating_add(1)),
+        Some('x') => 4,
+        Some(_) => 2,
+        None => 1,
+    };
+    cmp::min(len, chars.len().saturating_sub(index))
+}
+
+/// Length in chars of the numeric literal starting at `index`: an optional
+/// `0x`/`0o`/`0b` prefix, digits with `_` separators, a fraction and exponent
+/// for decimals, and a trailing type suffix such as `u8` or `f32`.
+///
+/// A decimal point only belongs to the number if it is the first one and a
+/// digit follows it, so that range operators such as the `..` in `0..10`
+/// and method calls such as `1.max(2)` end the number.
+fn number_len(chars: &[char], index: usize) -> usize {
+    let at = |offset: usize| chars.get(index.saturating_add(offset)).copied();
+    let mut len = 0;
+    let mut has_point = false;
+
+    let radix = match (at(0), at(1)) {
+        (Some('0'), Some('x')) | (Some('0'), Some('X')) => 16,
+        (Some('0'), Some('o')) | (Some('0'), Some('O')) => 8,
+        (Some('0'), Some('b')) | (Some('0'), Some('B')) => 2,
+        _ => 10,
+    };
+    if radix != 10 {
+        len = 2;
+    }
+
+    while let Some(c) = at(len) {
+        let is_point = radix == 10
+            && c == '.'
+            && !has_point
+            && at(len.saturating_add(1)).map_or(false, |c| c.is_ascii_digit());
+        if c.is_digit(radix) || c == '_' || is_point {
+            has_point |= is_point;
+            len += 1;
+        } else {
+            break;
+        }
+    }
+
+    if radix == 10 {
+        if let Some('e') | Some('E') = at(len) {
+            let sign = match at(len.saturating_add(1)) {
+                Some('+') | Some('-') => 1,
+                _ => 0,
+            };
+            let exponent = len.saturating_add(1).saturating_add(sign);
+            if at(exponent).map_or(false, |c| c.is_ascii_digit()) {
+                len = exponent;
+                while let Some(c) = at(len) {
+                    if c.is_ascii_digit() || c == '_' {
+                        len += 1;
+                    } else {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    let suffix = chars
+        .iter()
+        .skip(index.saturating_add(len))
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect::<String>();
+    let is_suffix = match suffix.as_str() {
+        "i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "u8" | "u16"
+        | "u32" | "u64" | "u128" | "usize" => true,
+        "f32" | "f64" => radix == 10,
+        _ => false,
+    };
+    if is_suffix {
+        len += suffix.len();
+    }
+
+    len
+}
+
+/// Cuts `text` down to the whole graphemes that fit in `width` columns.
+pub(crate) fn truncate_to_width(text: &mut String, width: usize) {
+    let mut columns: usize = 0;
+    let end = text.grapheme_indices(true).find_map(|(index, grapheme)| {
+        columns = columns.saturating_add(grapheme_width(grapheme));
+        if columns > width {
+            Some(index)
+        } else {
+            None
+        }
+    });
+    if let Some(end) = end {
+        text.truncate(end);
+    }
+}
+
+/// Number of terminal columns a single grapheme occupies.
+///
+/// A cluster is as wide as its widest code point, so combining marks and zero
+/// width joiners inside emoji sequences don't add columns of their own. An
+/// emoji presentation selector forces the cluster to be wide. Control chars
+/// take the one column of the picture drawn for them.
+pub(crate) fn grapheme_width(grapheme: &str) -> usize {
+    if grapheme == "\t" {
+        return TAB_WIDTH;
+    }
+    if grapheme.contains('\u{fe0f}') {
+        return 2;
+    }
+    grapheme
+        .chars()
+        .map(|c| c.width().unwrap_or(1))
+        .max()
+        .unwrap_or(0)
 }
 
+/// The char drawn in place of the control char `c`, so that control chars
+/// show up without the terminal acting on them, or `None` if `c` isn't one.
+/// Tabs are left to be drawn as spaces.
+pub(crate) fn control_picture(c: char) -> Option<char> {
+    match c {
+        '\t' => None,
+        '\u{7f}' => Some('\u{2421}'),
+        c if c < ' ' => {
+            std::char::from_u32(0x2400_u32.saturating_add(u32::from(c)))
+        },
+        c if c.is_control() => Some('\u{fffd}'),
+        _ => None,
+    }
+}
+
+/// Whether `c` separates words. Underscores join words, so identifiers like
+/// `snake_case` count as one.
 fn is_separator(c: char) -> bool {
-    c.is_ascii_punctuation() || c.is_ascii_whitespace()
+    c != '_' && (c.is_ascii_punctuation() || c.is_ascii_whitespace())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FileType;
+    use highlighting::Type;
+
+    /// The runs of `text` highlighted as in a file named `file`, as the
+    /// text of each run and its type.
+    fn spans(file: &str, text: &str) -> Vec<(String, Type)> {
+        let mut row = Row::from(text);
+        let file_type = FileType::from(file);
+        row.highlight(file_type.highlighting_options(), None, &[], false);
+        let mut spans: Vec<(String, Type)> = Vec::new();
+        for (c, hl_type) in text.chars().zip(row.highlighting.iter().copied()) {
+            match spans.last_mut() {
+                Some((run, last)) if *last == hl_type => run.push(c),
+                _ => spans.push((c.to_string(), hl_type)),
+            }
+        }
+        spans
+    }
+
+    fn span(text: &str, hl_type: Type) -> (String, Type) {
+        (text.to_string(), hl_type)
+    }
+
+    #[test]
+    fn escaped_quotes_stay_inside_the_string() {
+        assert_eq!(
+            spans("a.rs", r#""he said \"hi\"""#),
+            vec![
+                span("\"he said ", Type::String),
+                span("\\\"", Type::Escape),
+                span("hi", Type::String),
+                span("\\\"", Type::Escape),
+                span("\"", Type::String),
+            ]
+        );
+    }
+
+    #[test]
+    fn backslash_at_the_end_of_the_row() {
+        assert_eq!(
+            spans("a.rs", r#""open \"#),
+            vec![span("\"open ", Type::String), span("\\", Type::Escape)]
+        );
+        assert_eq!(
+            spans("a.rs", r"x = '\"),
+            vec![
+                span("x ", Type::None),
+                span("=", Type::Operator),
+                span(" '\\", Type::None),
+            ]
+        );
+    }
+
+    #[test]
+    fn number_literal_forms() {
+        for literal in &["0xFF", "0b1010", "0o7", "1_000", "1e10", "3.14f32"] {
+            assert_eq!(
+                spans("a.rs", literal),
+                vec![span(literal, Type::Number)],
+                "{}",
+                literal
+            );
+        }
+    }
+
+    #[test]
+    fn digits_in_a_name_are_not_a_number() {
+        assert_eq!(spans("a.rs", "foo2"), vec![span("foo2", Type::None)]);
+    }
+
+    #[test]
+    fn numbers_stop_at_range_operators() {
+        assert_eq!(
+            spans("a.rs", "0..10"),
+            vec![
+                span("0", Type::Number),
+                span("..", Type::Operator),
+                span("10", Type::Number),
+            ]
+        );
+        assert_eq!(
+            spans("a.rs", "1.0..2.0"),
+            vec![
+                span("1.0", Type::Number),
+                span("..", Type::Operator),
+                span("2.0", Type::Number),
+            ]
+        );
+        assert_eq!(spans("a.rs", "foo.bar"), vec![span("foo.bar", Type::None)]);
+    }
+
+    #[test]
+    fn makefile_targets_and_variables() {
+        assert_eq!(
+            spans("Makefile", "all build: $(OBJ) x.o"),
+            vec![
+                span("all build", Type::Tag),
+                span(": ", Type::None),
+                span("$(OBJ)", Type::Variable),
+                span(" x.o", Type::None),
+            ]
+        );
+        assert_eq!(
+            spans("Makefile", "\t$(CC) -o $@ $< # hi"),
+            vec![
+                span("\t", Type::None),
+                span("$(CC)", Type::Variable),
+                span(" -o ", Type::None),
+                span("$@", Type::Variable),
+                span(" ", Type::None),
+                span("$<", Type::Variable),
+                span(" ", Type::None),
+                span("# hi", Type::Comment),
+            ]
+        );
+        assert_eq!(
+            spans("Makefile", "CC := gcc"),
+            vec![span("CC := gcc", Type::None)]
+        );
+    }
+
+    #[test]
+    fn dockerfile_instructions_only_start_lines() {
+        assert_eq!(
+            spans("Dockerfile", "RUN cp a b && run copy"),
+            vec![
+                span("RUN", Type::PrimaryKeyword),
+                span(" cp a b && run copy", Type::None),
+            ]
+        );
+        assert_eq!(
+            spans("Dockerfile", "ENV A=$B"),
+            vec![
+                span("ENV", Type::PrimaryKeyword),
+                span(" A=", Type::None),
+                span("$B", Type::Variable),
+            ]
+        );
+    }
+
+    #[test]
+    fn keyword_at_the_end_of_the_row() {
+        assert_eq!(
+            spans("a.rs", "x; return"),
+            vec![
+                span("x; ", Type::None),
+                span("return", Type::PrimaryKeyword)
+            ]
+        );
+        assert_eq!(
+            spans("a.rs", "u8"),
+            vec![span("u8", Type::SecondaryKeyword)]
+        );
+    }
+
+    #[test]
+    fn keyword_prefix_of_a_name_is_not_a_keyword() {
+        assert_eq!(
+            spans("a.rs", "letter returned"),
+            vec![span("letter returned", Type::None)]
+        );
+    }
+
+    #[test]
+    fn combining_char_joins_the_grapheme_before_it() {
+        let mut row = Row::from("ae");
+        row.insert(2, '\u{301}');
+        assert_eq!(row.string, "ae\u{301}");
+        assert_eq!(row.len(), 2);
+
+        row.delete(1);
+        assert_eq!(row.string, "a");
+        assert_eq!(row.len(), 1);
+    }
+
+    #[test]
+    fn find_counts_graphemes_across_emoji() {
+        let row = Row::from("👍🏽 ok 👍 ok");
+        assert_eq!(row.find("ok", 0, SearchDirection::Forward), Some(2));
+        assert_eq!(row.find("ok", 3, SearchDirection::Forward), Some(7));
+        assert_eq!(row.find("ok", 9, SearchDirection::Backward), Some(7));
+    }
+
+    #[test]
+    fn find_skips_matches_inside_a_grapheme() {
+        let row = Row::from("👍🏽 👍");
+        assert_eq!(row.find("👍", 0, SearchDirection::Forward), Some(2));
+
+        let row = Row::from("cafe\u{301} cafe");
+        assert_eq!(row.find("cafe", 0, SearchDirection::Forward), Some(5));
+        assert_eq!(row.find("e", 0, SearchDirection::Forward), Some(8));
+        assert_eq!(row.find("e\u{301}", 0, SearchDirection::Forward), Some(3));
+        assert_eq!(row.find("cafe", 5, SearchDirection::Backward), None);
+    }
+
+    /// `rendered` without its escape sequences.
+    fn plain(rendered: &str) -> String {
+        let mut plain = String::new();
+        let mut chars = rendered.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                chars.by_ref().find(|c| *c == 'm');
+            } else {
+                plain.push(c);
+            }
+        }
+        plain
+    }
+
+    #[test]
+    fn render_window_past_the_start() {
+        let render = |text: &str, start, end| {
+            plain(&Row::from(text).render(start, end, false, false, None, None))
+        };
+        assert_eq!(render("e\u{301}xyz", 1, 3), "xy");
+        assert_eq!(render("ae\u{301}xyz", 1, 3), "e\u{301}x");
+        // A wide grapheme cut by either edge leaves spaces in its place.
+        assert_eq!(render("中文abc", 1, 4), " 文");
+        assert_eq!(render("中文abc", 0, 3), "中 ");
+        assert_eq!(render("中文abc", 3, 6), " ab");
+    }
+
+    #[test]
+    fn keyword_case_follows_the_filetype() {
+        assert_eq!(
+            spans("a.sql", "Select"),
+            vec![span("Select", Type::PrimaryKeyword)]
+        );
+        assert_eq!(spans("a.rs", "Select"), vec![span("Select", Type::None)]);
+        assert_eq!(spans("a.rs", "Let"), vec![span("Let", Type::None)]);
+    }
+
+    #[test]
+    fn longest_operator_wins() {
+        assert_eq!(
+            spans("a.js", "a>>>=b"),
+            vec![
+                span("a", Type::None),
+                span(">>>=", Type::Operator),
+                span("b", Type::None),
+            ]
+        );
+        assert_eq!(
+            spans("a.go", "x := <-ch"),
+            vec![
+                span("x ", Type::None),
+                span(":=", Type::Operator),
+                span(" ", Type::None),
+                span("<-", Type::Operator),
+                span("ch", Type::None),
+            ]
+        );
+    }
+
+    #[test]
+    fn truncating_keeps_whole_wide_graphemes() {
+        let mut name = String::from("日本語のファイル名.txt");
+        truncate_to_width(&mut name, 5);
+        assert_eq!(name, "日本");
+        let mut emoji = String::from("a👍🏽b");
+        truncate_to_width(&mut emoji, 2);
+        assert_eq!(emoji, "a");
+        let mut short = String::from("ok");
+        truncate_to_width(&mut short, 20);
+        assert_eq!(short, "ok");
+    }
 }
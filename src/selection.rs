@@ -0,0 +1,55 @@
+use crate::Position;
+
+use std::cmp;
+
+/// A range of the document between where it was started, the `anchor`, and
+/// where it currently ends, the `head`. The head may come before the anchor.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Selection {
+    pub anchor: Position,
+    pub head: Position,
+}
+
+impl Selection {
+    #[must_use]
+    pub const fn new(anchor: Position, head: Position) -> Self {
+        Self { anchor, head }
+    }
+
+    /// The earlier of the two ends.
+    #[must_use]
+    pub fn start(&self) -> &Position {
+        cmp::min(&self.anchor, &self.head)
+    }
+
+    /// The later of the two ends, itself not selected.
+    #[must_use]
+    pub fn end(&self) -> &Position {
+        cmp::max(&self.anchor, &self.head)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+}
+
+/// A rectangle of the document, spanning the rows from `top_left.y` to
+/// `bottom_right.y` and the display columns from `top_left.x` up to, but not
+/// including, `bottom_right.x`. Columns rather than grapheme indexes keep
+/// the rectangle straight on screen across tabs and wide characters.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BlockSelection {
+    pub top_left: Position,
+    pub bottom_right: Position,
+}
+
+impl BlockSelection {
+    /// The rectangle with `a` and `b` as opposite corners, in either order.
+    #[must_use]
+    pub fn new(a: &Position, b: &Position) -> Self {
+        Self {
+            top_left: Position::new(cmp::min(a.x, b.x), cmp::min(a.y, b.y)),
+            bottom_right: Position::new(cmp::max(a.x, b.x), cmp::max(a.y, b.y)),
+        }
+    }
+}
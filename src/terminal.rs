@@ -1,20 +1,44 @@
 use crate::Position;
 
 use anyhow::Result;
+use serde::Deserialize;
 use termion::{
     color,
     event::Key,
-    input::TermRead,
+    input::{Keys, TermRead},
     raw::{IntoRawMode, RawTerminal},
+    screen::AlternateScreen,
 };
 
-use std::io::{self, Write};
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    panic::{self, AssertUnwindSafe},
+    thread,
+    time::Duration,
+};
+
+/// The smallest text area the editor draws in: one row above the status and
+/// message bars, wide enough for the status bar to make sense.
+const MIN_WIDTH: u16 = 20;
+const MIN_HEIGHT: u16 = 1;
+
+/// How long the screen stays inverted for a visual bell.
+const FLASH_DURATION: Duration = Duration::from_millis(100);
+
+thread_local! {
+    static PANIC_REPORT: RefCell<Option<String>> = RefCell::new(None);
+}
 
+#[derive(Clone, Copy)]
 pub struct Size {
     pub(crate) width: u16,
     pub(crate) height: u16,
 }
 
+/// Takes the terminal's `(columns, rows)`, leaving out the two rows of the
+/// status and message bars. A terminal with fewer rows than that gets a
+/// height of 0.
 impl From<(u16, u16)> for Size {
     fn from(size: (u16, u16)) -> Self {
         Size {
@@ -24,9 +48,43 @@ impl From<(u16, u16)> for Size {
     }
 }
 
+impl Size {
+    /// Whether the text area is at least the minimum size the editor draws
+    /// in.
+    pub const fn is_usable(&self) -> bool {
+        self.width >= MIN_WIDTH && self.height >= MIN_HEIGHT
+    }
+}
+
+/// How [`Terminal::flash`] gets the user's attention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bell {
+    /// Inverts the screen's colors for a moment, which keeps quiet in
+    /// shared spaces.
+    Visual,
+    /// Rings the terminal bell.
+    Audible,
+}
+
+impl Default for Bell {
+    fn default() -> Self {
+        Self::Visual
+    }
+}
+
 pub struct Terminal {
     size: Size,
-    _stdout: RawTerminal<io::Stdout>,
+    bell: Bell,
+    /// Raw mode on the alternate screen; dropping it restores the original
+    /// screen contents and terminal mode.
+    stdout: AlternateScreen<RawTerminal<io::Stdout>>,
+    /// Kept across reads, as the iterator may have read ahead past the key it
+    /// returned when several arrive at once.
+    keys: Keys<io::Stdin>,
+    /// Output queued up since the last `flush`, so that a whole frame reaches
+    /// the terminal in one write instead of flickering in piece by piece.
+    buffer: String,
 }
 
 impl Terminal {
@@ -34,65 +92,149 @@ impl Terminal {
         let size = Size::from(termion::terminal_size()?);
         Ok(Self {
             size,
-            _stdout: io::stdout().into_raw_mode()?,
+            bell: Bell::default(),
+            stdout: AlternateScreen::from(io::stdout().into_raw_mode()?),
+            keys: io::stdin().keys(),
+            buffer: String::new(),
+        })
+    }
+
+    /// Runs `f`, holding back the report of any panic inside it until
+    /// everything `f` owned, the `Terminal` included, has been dropped. The
+    /// message then prints on a restored terminal instead of a raw one.
+    pub fn with_panic_guard<T>(f: impl FnOnce() -> T) -> T {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(|info| {
+            let message = info.to_string();
+            PANIC_REPORT.with(|report| *report.borrow_mut() = Some(message));
+        }));
+
+        let result = panic::catch_unwind(AssertUnwindSafe(f));
+        panic::set_hook(default_hook);
+
+        result.unwrap_or_else(|payload| {
+            if let Some(message) =
+                PANIC_REPORT.with(|report| report.borrow_mut().take())
+            {
+                eprintln!("{}", message);
+            }
+            panic::resume_unwind(payload)
         })
     }
 
+    pub fn set_bell(&mut self, bell: Bell) {
+        self.bell = bell;
+    }
+
+    /// Signals that something didn't work, the way the bell is set to. This
+    /// bypasses the output queued by `write_buf`, which is left for the
+    /// next `flush`, and the screen is back to normal by the time this
+    /// returns.
+    pub(crate) fn flash(&mut self) -> Result<(), io::Error> {
+        match self.bell {
+            Bell::Audible => self.stdout.write_all(b"\x07")?,
+            Bell::Visual => {
+                // Reverse video mode, DECSCNM, swaps the colors of the whole
+                // screen without touching its contents.
+                self.stdout.write_all(b"\x1b[?5h")?;
+                self.stdout.flush()?;
+                thread::sleep(FLASH_DURATION);
+                self.stdout.write_all(b"\x1b[?5l")?;
+            },
+        }
+        self.stdout.flush()
+    }
+
     pub(crate) const fn size(&self) -> &Size {
         &self.size
     }
 
-    pub(crate) fn clear_screen() {
-        print!("{}", termion::clear::All);
+    /// Queues `s` to be written on the next `flush`.
+    pub fn write_buf(&mut self, s: &str) {
+        self.buffer.push_str(s);
+    }
+
+    pub(crate) fn clear_screen(&mut self) {
+        self.write_buf(termion::clear::All.as_ref());
     }
 
-    pub(crate) fn clear_current_line() {
-        print!("{}", termion::clear::CurrentLine);
+    pub(crate) fn clear_current_line(&mut self) {
+        self.write_buf(termion::clear::CurrentLine.as_ref());
     }
 
-    pub fn set_bg_color(color: color::Rgb) {
-        print!("{}", color::Bg(color));
+    pub fn set_bg_color(&mut self, color: color::Rgb) {
+        self.write_buf(&color::Bg(color).to_string());
     }
 
-    pub fn reset_bg_color() {
-        print!("{}", color::Bg(color::Reset));
+    pub fn reset_bg_color(&mut self) {
+        self.write_buf(&color::Bg(color::Reset).to_string());
     }
 
-    pub fn set_fg_color(color: color::Rgb) {
-        print!("{}", color::Fg(color));
+    pub fn set_fg_color(&mut self, color: color::Rgb) {
+        self.write_buf(&color::Fg(color).to_string());
     }
 
-    pub fn reset_fg_color() {
-        print!("{}", color::Fg(color::Reset));
+    pub fn reset_fg_color(&mut self) {
+        self.write_buf(&color::Fg(color::Reset).to_string());
     }
 
     #[allow(clippy::cast_possible_truncation)]
-    pub(crate) fn cursor_position(position: &Position) {
+    pub(crate) fn cursor_position(&mut self, position: &Position) {
         let Position { mut x, mut y } = position;
         x = x.saturating_add(1);
         y = y.saturating_add(1);
         let x = x as u16;
         let y = y as u16;
-        print!("{}", termion::cursor::Goto(x, y));
+        self.write_buf(&termion::cursor::Goto(x, y).to_string());
     }
 
-    pub(crate) fn cursor_hide() {
-        print!("{}", termion::cursor::Hide);
+    pub(crate) fn cursor_hide(&mut self) {
+        self.write_buf(termion::cursor::Hide.as_ref());
     }
 
-    pub(crate) fn cursor_show() {
-        print!("{}", termion::cursor::Show);
+    pub(crate) fn cursor_show(&mut self) {
+        self.write_buf(termion::cursor::Show.as_ref());
     }
 
-    pub(crate) fn flush() -> Result<(), io::Error> {
-        io::stdout().flush()
+    /// Writes out everything queued by `write_buf` at once.
+    pub(crate) fn flush(&mut self) -> Result<(), io::Error> {
+        self.stdout.write_all(self.buffer.as_bytes())?;
+        self.buffer.clear();
+        self.stdout.flush()
     }
 
-    pub(crate) fn read_key() -> Result<Key, io::Error> {
+    pub(crate) fn read_key(&mut self) -> Result<Key, io::Error> {
         loop {
-            if let Some(key) = io::stdin().lock().keys().next() {
+            if let Some(key) = self.keys.next() {
                 return key;
             }
         }
     }
 }
+
+impl Drop for Terminal {
+    /// Undoes what the editor may have left behind. The alternate screen and
+    /// raw mode are left when `stdout` is dropped right after this.
+    fn drop(&mut self) {
+        self.cursor_show();
+        self.reset_fg_color();
+        self.reset_bg_color();
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_leaves_out_the_bars_without_underflowing() {
+        let size = Size::from((80, 1));
+        assert_eq!((size.width, size.height), (80, 0));
+        assert!(!size.is_usable());
+
+        let size = Size::from((80, 24));
+        assert_eq!((size.width, size.height), (80, 22));
+        assert!(size.is_usable());
+    }
+}
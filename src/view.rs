@@ -0,0 +1,95 @@
+use crate::{Document, Position, Row, Size};
+
+use std::cmp;
+
+/// The window of a document that is currently visible on screen.
+pub struct ViewState {
+    pub offset: Position,
+    pub size: Size,
+    /// Number of rows to keep visible above and below the cursor.
+    pub scroll_margin: usize,
+}
+
+impl ViewState {
+    pub fn new(size: Size) -> Self {
+        Self {
+            offset: Position::default(),
+            size,
+            scroll_margin: 0,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn width(&self) -> usize {
+        self.size.width as usize
+    }
+
+    #[inline]
+    pub(crate) fn height(&self) -> usize {
+        self.size.height as usize
+    }
+
+    /// The scroll margin, shrunk so that it can always be honored on a short
+    /// screen.
+    #[allow(clippy::integer_division, clippy::arithmetic_side_effects)]
+    fn margin(&self) -> usize {
+        cmp::min(self.scroll_margin, self.height().saturating_sub(1) / 2)
+    }
+
+    /// Scrolls the minimum amount needed to keep `cursor` on screen, along
+    /// with up to `scroll_margin` lines above and below it. `last_line` is
+    /// the last screen line of the document: near the end, the margin below
+    /// shrinks to the lines there are, rather than scrolling past the end.
+    pub fn scroll_to(&mut self, cursor: &Position, last_line: usize) {
+        let Position { x, y } = *cursor;
+        let width = self.width();
+        let height = self.height();
+        let margin = self.margin();
+        let margin_below = cmp::min(margin, last_line.saturating_sub(y));
+        let offset = &mut self.offset;
+
+        if y < offset.y.saturating_add(margin) {
+            offset.y = y.saturating_sub(margin);
+        } else if y.saturating_add(margin_below)
+            >= offset.y.saturating_add(height)
+        {
+            offset.y = y
+                .saturating_add(margin_below)
+                .saturating_add(1)
+                .saturating_sub(height);
+        }
+
+        if x < offset.x {
+            offset.x = x;
+        } else if x >= offset.x.saturating_add(width) {
+            offset.x = x.saturating_sub(width).saturating_add(1);
+        }
+    }
+
+    /// Scrolls so that `cursor` sits in the middle of the screen, as wanted
+    /// after a jump.
+    #[allow(clippy::integer_division, clippy::arithmetic_side_effects)]
+    pub fn center_on(&mut self, cursor: &Position, last_line: usize) {
+        self.offset.y = cursor.y.saturating_sub(self.height() / 2);
+        self.scroll_to(cursor, last_line);
+    }
+
+    /// Whether `position` is currently on screen.
+    pub fn contains(&self, position: &Position) -> bool {
+        position.y >= self.offset.y
+            && position.y < self.offset.y.saturating_add(self.height())
+            && position.x >= self.offset.x
+            && position.x < self.offset.x.saturating_add(self.width())
+    }
+
+    /// The document rows that fall inside the view, top to bottom, with
+    /// their line numbers. The view's offset counts screen lines, which
+    /// differ from document lines when some are folded away.
+    pub fn visible_rows<'d>(
+        &self,
+        doc: &'d Document,
+    ) -> impl Iterator<Item = (usize, &'d Row)> {
+        doc.visible_rows_from(doc.line_at_display(self.offset.y))
+            .take(self.height())
+    }
+}